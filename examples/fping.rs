@@ -0,0 +1,67 @@
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use futures::{pin_mut, stream::StreamExt};
+use gumdrop::Options;
+use tokio::net::lookup_host;
+use netdiag::{Bind, Limiter, Ping, Pinger, PingStats};
+use netdiag::ping::Reply;
+
+#[derive(Debug, Options)]
+pub struct Args {
+    #[options()]                 help:     bool,
+    #[options(default = "4")]    count:    usize,
+    #[options(default = "1000")] interval: u64,
+    #[options(default = "250")]  expiry:   u64,
+    #[options(default = "100")]  rate:     u32,
+    #[options(free, required)]   hosts:    Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse_args_default_or_exit();
+    let Args { count, interval, expiry, rate, hosts, .. } = args;
+
+    env_logger::init();
+
+    let interval = Duration::from_millis(interval);
+    let expiry   = Duration::from_millis(expiry);
+
+    let mut pings = Vec::new();
+    for host in &hosts {
+        let addr = format!("{}:0", host);
+        let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
+            anyhow!("invalid target: {}", host)
+        })?.ip();
+        pings.push(Ping { addr, count, expiry, interval, ..Default::default() });
+    }
+
+    let pinger  = Pinger::new(&Bind::default()).await?;
+    let limiter = Limiter::new(rate);
+    let stream  = pinger.ping_all(&pings, &limiter);
+    pin_mut!(stream);
+
+    let mut stats = vec![PingStats::new(); pings.len()];
+
+    while let Some((index, item)) = stream.next().await {
+        let reply = item?;
+        let host  = &hosts[index];
+        match &reply {
+            Reply::Echo(echo) => println!("{} : [{}], {} bytes, {:0.2?}", host, echo.seq, echo.size, echo.rtt),
+            Reply::Error(err) => println!("{} : [{}], icmp type {} code {} from {}", host, err.seq, err.kind, err.code, err.addr),
+            Reply::Timeout(n) => println!("{} : [{}], timed out", host, n),
+        }
+        stats[index].record(&reply);
+    }
+
+    println!();
+
+    for (host, stats) in hosts.iter().zip(&stats) {
+        let min = stats.min().unwrap_or_default();
+        let avg = stats.avg().unwrap_or_default();
+        let max = stats.max().unwrap_or_default();
+        let (sent, recv, loss) = (stats.sent(), stats.received(), stats.loss());
+        println!("{} : xmt/rcv/%loss = {}/{}/{:.0}%, min/avg/max = {:0.2?}/{:0.2?}/{:0.2?}", host, sent, recv, loss, min, avg, max);
+    }
+
+    Ok(())
+}
@@ -3,28 +3,41 @@ use anyhow::{anyhow, Result};
 use futures::{pin_mut, stream::StreamExt};
 use gumdrop::Options;
 use tokio::net::lookup_host;
-use tokio::time::sleep;
 use netdiag::{Bind, Knock, Knocker};
+use netdiag::knock::{Backoff, Outcome, Response};
 
 #[derive(Debug, Options)]
 pub struct Args {
-    #[options()]                help:   bool,
-    #[options(default = "4")]   count:  usize,
-    #[options(default = "500")] delay:  u64,
-    #[options(default = "250")] expiry: u64,
-    #[options(free, required)]  host:   String,
-    #[options(free, required)]  port:   u16,
+    #[options()]                  help:     bool,
+    #[options(default = "4")]     count:    usize,
+    #[options(default = "500")]   interval: u64,
+    #[options()]                  deadline: Option<u64>,
+    #[options(default = "250")]   expiry:   u64,
+    #[options(default = "0")]     retries:  usize,
+    #[options(default = "fixed")] backoff:  String,
+    #[options(no_short)]          reset:    bool,
+    #[options(free, required)]    host:     String,
+    #[options(free, required)]    port:     u16,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse_args_default_or_exit();
-    let Args { count, delay, expiry, host, port, .. } = args;
+    let Args { count, interval, deadline, expiry, retries, backoff, reset, host, port, .. } = args;
 
     env_logger::init();
 
-    let delay  = Duration::from_millis(delay);
-    let expiry = Duration::from_millis(expiry);
+    let interval = Duration::from_millis(interval);
+    let deadline = deadline.map(Duration::from_secs);
+    let expiry   = Duration::from_millis(expiry);
+
+    let backoff = match backoff.to_lowercase().as_str() {
+        "fixed"       => Backoff::Fixed,
+        "exponential" => Backoff::Exponential,
+        list          => Backoff::Custom(list.split(',').map(|ms| {
+            Ok(Duration::from_millis(ms.trim().parse()?))
+        }).collect::<Result<_>>()?),
+    };
 
     let addr = format!("{}:{}", host, port);
     let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
@@ -34,16 +47,32 @@ async fn main() -> Result<()> {
     println!("knocking {} ({})", host, addr);
 
     let knocker = Knocker::new(&Bind::default()).await?;
-    let knock   = Knock { addr, port, count, expiry };
+    let knock   = Knock { addr, port, count, expiry, interval, deadline, retries, backoff, reset };
     let stream  = knocker.knock(&knock).await?.enumerate();
     pin_mut!(stream);
 
     while let Some((n, item)) = stream.next().await {
-        match item? {
-            Some(d) => println!("seq {} RTT {:0.2?} ", n, d),
-            None    => println!("seq {} timeout", n),
+        let Response { outcome, attempt, handshake, .. } = item?;
+
+        print!("seq {} attempt {} ", n, attempt);
+
+        match outcome {
+            Outcome::Open(rtt)               => println!("open RTT {:0.2?}", rtt),
+            Outcome::Closed(rtt)             => println!("closed RTT {:0.2?}", rtt),
+            Outcome::Filtered                => println!("filtered"),
+            Outcome::Unreachable(from, code) => println!("unreachable from {} code {}", from, code),
+            Outcome::Timeout                 => println!("timeout"),
+        }
+
+        if let Some(hs) = handshake {
+            let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_owned());
+            let ttl  = show(hs.ttl.map(|n| n.to_string()));
+            let mss  = show(hs.mss.map(|n| n.to_string()));
+            let ws   = show(hs.scale.map(|n| n.to_string()));
+            let ts   = show(hs.timestamp.map(|(val, ecr)| format!("{}/{}", val, ecr)));
+            println!("    ttl {} win {} mss {} ws {} sack {} ts {}", ttl, hs.window, mss, ws, hs.sack, ts);
+            println!("    fingerprint {}", hs.fingerprint());
         }
-        sleep(delay).await;
     }
 
     Ok(())
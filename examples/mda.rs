@@ -0,0 +1,65 @@
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use gumdrop::Options;
+use tokio::net::lookup_host;
+use netdiag::{Bind, Mda, Protocol, Tracer};
+
+#[derive(Debug, Options)]
+pub struct Args {
+    #[options()]                 help:       bool,
+    #[options(default = "UDP")]  proto:      String,
+    #[options()]                 port:       u16,
+    #[options(default = "0.95")] confidence: f64,
+    #[options(default = "256")]  flows:      u16,
+    #[options(default = "30")]   limit:      u8,
+    #[options(default = "50")]   interval:   u64,
+    #[options()]                 deadline:   Option<u64>,
+    #[options(default = "250")]  expiry:     u64,
+    #[options(free, required)]   host:       String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse_args_default_or_exit();
+    let Args { proto, port, confidence, flows, limit, interval, deadline, expiry, host, .. } = args;
+
+    env_logger::init();
+
+    let proto = match proto.to_uppercase().as_str() {
+        "ICMP"            => Protocol::ICMP,
+        "UDP" if port > 0 => Protocol::UDP(port),
+        _                 => Protocol::default(),
+    };
+
+    let interval = Duration::from_millis(interval);
+    let deadline = deadline.map(Duration::from_secs);
+    let expiry   = Duration::from_millis(expiry);
+
+    let addr = format!("{}:0", host);
+    let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
+        anyhow!("invalid target")
+    })?.ip();
+
+    println!("tracing {} ({})", host, addr);
+
+    let tracer = Tracer::new(&Bind::default()).await?;
+    let mda    = Mda { proto, addr, confidence, flows, limit, expiry, interval, deadline };
+    let graph  = tracer.mda(mda).await?;
+
+    for hop in &graph.hops {
+        let lost = format!("{}/{} lost", hop.lost, hop.probes);
+        println!("[{:>3}] {}", hop.ttl, lost);
+        for interface in &hop.interfaces {
+            let flows = interface.flows.len();
+            println!("      {:32} {:>0.2?} ({} flows)", interface.addr, interface.rtt, flows);
+        }
+    }
+
+    println!();
+
+    for link in &graph.links {
+        println!("[{:>3}] {} -> {} ({} flows)", link.ttl, link.from, link.to, link.flows.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use futures::{pin_mut, StreamExt};
+use gumdrop::Options;
+use tokio::net::lookup_host;
+use netdiag::{Bind, Dns, Mtr, Names, Protocol, Tracer};
+
+#[derive(Debug, Options)]
+pub struct Args {
+    #[options()]                help:     bool,
+    #[options(default = "UDP")] proto:    String,
+    #[options()]                port:     u16,
+    #[options(default = "10")]  count:    usize,
+    #[options(default = "30")]  limit:    u8,
+    #[options(default = "100")] interval: u64,
+    #[options()]                deadline: Option<u64>,
+    #[options(default = "500")] expiry:   u64,
+    #[options()]                window:   usize,
+    #[options(no_short)]        resolve:  bool,
+    #[options(no_short)]        dns:      Option<SocketAddr>,
+    #[options(free, required)]  host:     String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse_args_default_or_exit();
+    let Args { proto, port, count, limit, interval, deadline, expiry, window, resolve, dns, host, .. } = args;
+
+    env_logger::init();
+
+    let proto = match proto.to_uppercase().as_str() {
+        "ICMP"            => Protocol::ICMP,
+        "TCP" if port > 0 => Protocol::TCP(port),
+        "UDP" if port > 0 => Protocol::UDP(port),
+        _                 => Protocol::default(),
+    };
+
+    let interval = Duration::from_millis(interval);
+    let deadline = deadline.map(Duration::from_secs);
+    let expiry   = Duration::from_millis(expiry);
+
+    let addr = format!("{}:0", host);
+    let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
+        anyhow!("invalid target")
+    })?.ip();
+
+    let names = match (resolve, dns) {
+        (_,     Some(dns)) => Some(Names::new(Dns::new(dns), Duration::from_secs(5))),
+        (true,  None)      => Some(Names::new(Dns::system()?, Duration::from_secs(5))),
+        (false, None)      => None,
+    };
+
+    let tracer = Tracer::new(&Bind::default()).await?;
+    let source = tracer.reserve(proto, addr).await?;
+
+    let mut probe = source.paris(0)?;
+
+    let mtr    = Mtr { addr, limit, expiry, interval, deadline, window };
+    let stream = tracer.mtr(&mut probe, &mtr).take(count);
+    pin_mut!(stream);
+
+    while let Some(report) = stream.next().await {
+        let report = report?;
+
+        println!("mtr {} ({}) round {}", host, addr, report.round);
+        println!("{:>4}  {:40} {:>6} {:>4} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                 "", "Host", "Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev", "Jitter");
+
+        for hop in &report.hops {
+            let stats = &hop.stats;
+            let ms    = |d: Option<Duration>| match d {
+                Some(d) => format!("{:.1}", d.as_secs_f64() * 1000.0),
+                None    => "-".to_owned(),
+            };
+
+            let host = match hop.responders.first() {
+                Some(responder) => label(names.as_ref(), responder.addr),
+                None            => "???".to_owned(),
+            };
+
+            println!("{:>3}.  {:40} {:>5.1}% {:>4} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                     hop.ttl, host, stats.loss, stats.sent,
+                     ms(stats.last), ms(stats.avg), ms(stats.min),
+                     ms(stats.max), ms(stats.mdev), ms(stats.jitter));
+
+            for responder in hop.responders.iter().skip(1) {
+                let fresh = match responder.fresh(report.round) {
+                    true  => " (new)",
+                    false => "",
+                };
+                println!("      {}{}", label(names.as_ref(), responder.addr), fresh);
+            }
+        }
+
+        println!();
+    }
+
+    Ok(())
+}
+
+fn label(names: Option<&Names>, addr: IpAddr) -> String {
+    let name = names.and_then(|names| {
+        names.prefetch(addr);
+        names.cached(addr)
+    });
+
+    match name {
+        Some(name) => format!("{} ({})", name, addr),
+        None       => addr.to_string(),
+    }
+}
@@ -3,27 +3,43 @@ use anyhow::{anyhow, Result};
 use futures::{pin_mut, stream::StreamExt};
 use gumdrop::Options;
 use tokio::net::lookup_host;
-use tokio::time::sleep;
-use netdiag::{Bind, Ping, Pinger};
+use netdiag::{Bind, Ping, Pinger, PingStats};
+use netdiag::ping::{Echo, IcmpError, Mode, Reply};
 
 #[derive(Debug, Options)]
 pub struct Args {
-    #[options()]                help:   bool,
-    #[options(default = "4")]   count:  usize,
-    #[options(default = "500")] delay:  u64,
-    #[options(default = "250")] expiry: u64,
-    #[options(free, required)]  host:   String,
+    #[options()]                 help:     bool,
+    #[options(default = "4")]    count:    usize,
+    #[options(default = "1000")] interval: u64,
+    #[options()]                 deadline: Option<u64>,
+    #[options()]                 grace:    u64,
+    #[options(default = "250")]  expiry:   u64,
+    #[options(default = "56")]   size:     usize,
+    #[options()]                 pattern:  Option<String>,
+    #[options(default = "64")]   ttl:      u8,
+    #[options(no_short)]         dscp:     u8,
+    #[options(no_short)]         ecn:      u8,
+    #[options(no_short)]         df:       bool,
+    #[options(no_short)]         dgram:    bool,
+    #[options(free, required)]   host:     String,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse_args_default_or_exit();
-    let Args { count, delay, expiry, host, .. } = args;
+    let Args { count, interval, deadline, grace, expiry, size, pattern, ttl, dscp, ecn, df, dgram, host, .. } = args;
 
     env_logger::init();
 
-    let delay  = Duration::from_millis(delay);
-    let expiry = Duration::from_millis(expiry);
+    let interval = Duration::from_millis(interval);
+    let deadline = deadline.map(Duration::from_secs);
+    let grace    = Duration::from_millis(grace);
+    let expiry   = Duration::from_millis(expiry);
+
+    let pattern = match pattern {
+        Some(hex) => decode(&hex)?,
+        None      => Vec::new(),
+    };
 
     let addr = format!("{}:0", host);
     let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
@@ -32,18 +48,56 @@ async fn main() -> Result<()> {
 
     println!("pinging {} ({})", host, addr);
 
-    let pinger = Pinger::new(&Bind::default()).await?;
-    let ping   = Ping { addr, count, expiry };
-    let stream = pinger.ping(&ping).enumerate();
+    let mode = match dgram {
+        true  => Mode::Dgram,
+        false => Mode::Auto,
+    };
+
+    let pinger = Pinger::with_mode(&Bind::default(), mode).await?;
+    let ping   = Ping { addr, count, expiry, interval, deadline, grace, size, pattern, ttl, dscp, ecn, df };
+    let stream = pinger.ping(&ping);
     pin_mut!(stream);
 
-    while let Some((n, item)) = stream.next().await {
-        match item? {
-            Some(d) => println!("seq {} RTT {:0.2?} ", n, d),
-            None    => println!("seq {} timeout", n),
+    let mut stats = PingStats::new();
+
+    while let Some(item) = stream.next().await {
+        let reply = item?;
+        match &reply {
+            Reply::Echo(echo) => print(echo),
+            Reply::Error(err) => error(err),
+            Reply::Timeout(n) => println!("seq {} timeout", n),
         }
-        sleep(delay).await;
+        stats.record(&reply);
+    }
+
+    println!("--- {} ping statistics ---", host);
+    println!("{}", stats);
+
+    if let [Some(p50), Some(p90), Some(p99)] = stats.percentiles(&[50.0, 90.0, 99.0])[..] {
+        println!("rtt p50/p90/p99 = {:0.2?}/{:0.2?}/{:0.2?}", p50, p90, p99);
     }
 
     Ok(())
 }
+
+fn print(echo: &Echo) {
+    let Echo { seq, addr, ttl, tos, size, rtt, dup, late, corrupt, .. } = echo;
+    let dup     = if *dup     { " (DUP!)"     } else { "" };
+    let late    = if *late    { " (LATE!)"    } else { "" };
+    let corrupt = if *corrupt { " (CORRUPT!)" } else { "" };
+    println!("{} bytes from {}: seq {} ttl {} tos {:#04x} RTT {:0.2?}{}{}{}", size, addr, seq, ttl, tos, rtt, dup, late, corrupt);
+}
+
+fn error(err: &IcmpError) {
+    let IcmpError { seq, addr, kind, code, rtt, .. } = err;
+    println!("from {}: seq {} icmp type {} code {} RTT {:0.2?}", addr, seq, kind, code, rtt);
+}
+
+fn decode(hex: &str) -> Result<Vec<u8>> {
+    hex.as_bytes().chunks(2).map(|chunk| {
+        let byte = std::str::from_utf8(chunk).ok().filter(|b| b.len() == 2);
+        byte.and_then(|b| u8::from_str_radix(b, 16).ok()).ok_or_else(|| {
+            anyhow!("invalid pattern: {}", hex)
+        })
+    }).collect()
+}
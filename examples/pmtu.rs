@@ -0,0 +1,72 @@
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use gumdrop::Options;
+use tokio::net::lookup_host;
+use netdiag::{Bind, Pmtu, Protocol, Tracer};
+
+#[derive(Debug, Options)]
+pub struct Args {
+    #[options()]                 help:     bool,
+    #[options(default = "UDP")]  proto:    String,
+    #[options()]                 port:     u16,
+    #[options(default = "1500")] size:     usize,
+    #[options(default = "2")]    probes:   usize,
+    #[options(default = "30")]   limit:    u8,
+    #[options(default = "50")]   interval: u64,
+    #[options()]                 deadline: Option<u64>,
+    #[options(default = "500")]  expiry:   u64,
+    #[options(free, required)]   host:     String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse_args_default_or_exit();
+    let Args { proto, port, size, probes, limit, interval, deadline, expiry, host, .. } = args;
+
+    env_logger::init();
+
+    let proto = match proto.to_uppercase().as_str() {
+        "ICMP"            => Protocol::ICMP,
+        "UDP" if port > 0 => Protocol::UDP(port),
+        _                 => Protocol::default(),
+    };
+
+    let interval = Duration::from_millis(interval);
+    let deadline = deadline.map(Duration::from_secs);
+    let expiry   = Duration::from_millis(expiry);
+
+    let addr = format!("{}:0", host);
+    let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
+        anyhow!("invalid target")
+    })?.ip();
+
+    println!("tracepath {} ({})", host, addr);
+
+    let tracer = Tracer::new(&Bind::default()).await?;
+    let pmtu   = Pmtu { proto, addr, size, probes, limit, expiry, interval, deadline };
+    let path   = tracer.pmtu(pmtu).await?;
+
+    for hop in &path.hops {
+        for size in &hop.local {
+            println!("[{:>3}] local MTU below {}", hop.ttl, size);
+        }
+
+        for node in &hop.reported {
+            let mtu = node.mtu.unwrap_or_default();
+            println!("[{:>3}] {} reports MTU {}", hop.ttl, node.addr, mtu);
+        }
+
+        if let Some(size) = hop.blackhole {
+            println!("[{:>3}] black hole: {} byte probes lost without ICMP error", hop.ttl, size);
+        }
+
+        match &hop.node {
+            Some(node) => println!("[{:>3}] {:32} {:>0.2?} pmtu {}", hop.ttl, node.addr, node.rtt, hop.mtu),
+            None       => println!("[{:>3}] {:32} {:>8} pmtu {}", hop.ttl, "*", "", hop.mtu),
+        }
+    }
+
+    println!("path MTU {}", path.mtu);
+
+    Ok(())
+}
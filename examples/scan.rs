@@ -0,0 +1,73 @@
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use futures::{pin_mut, stream::StreamExt};
+use gumdrop::Options;
+use tokio::net::lookup_host;
+use netdiag::{Bind, Knocker, Limiter};
+use netdiag::knock::{Outcome, Response, Scan, Target};
+
+#[derive(Debug, Options)]
+pub struct Args {
+    #[options()]                   help:     bool,
+    #[options(default = "1-1024")] ports:    String,
+    #[options(default = "250")]    expiry:   u64,
+    #[options(default = "0")]      retries:  usize,
+    #[options(default = "64")]     parallel: usize,
+    #[options(default = "100")]    rate:     u32,
+    #[options(no_short)]           reset:    bool,
+    #[options(no_short)]           all:      bool,
+    #[options(free, required)]     hosts:    Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse_args_default_or_exit();
+    let Args { ports, expiry, retries, parallel, rate, reset, all, hosts, .. } = args;
+
+    env_logger::init();
+
+    let expiry = Duration::from_millis(expiry);
+    let ports  = parse(&ports)?;
+
+    let mut targets = Vec::new();
+    for host in &hosts {
+        let addr = format!("{}:0", host);
+        let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
+            anyhow!("invalid target: {}", host)
+        })?.ip();
+        targets.push(Target { addr, ports: ports.clone() });
+    }
+
+    let knocker = Knocker::new(&Bind::default()).await?;
+    let limiter = Limiter::new(rate);
+    let scan    = Scan { expiry, retries, reset, parallel, ..Default::default() };
+    let stream  = knocker.scan(&targets, &scan, &limiter).await?;
+    pin_mut!(stream);
+
+    while let Some((dst, item)) = stream.next().await {
+        let Response { outcome, attempt, handshake, .. } = item?;
+        let fp = handshake.map(|hs| hs.fingerprint()).unwrap_or_default();
+
+        match outcome {
+            Outcome::Open(rtt)               => println!("{} open, {:0.2?} (attempt {}) [{}]", dst, rtt, attempt, fp),
+            Outcome::Closed(rtt) if all      => println!("{} closed, {:0.2?}", dst, rtt),
+            Outcome::Filtered if all         => println!("{} filtered", dst),
+            Outcome::Unreachable(from, code) => println!("{} unreachable from {} code {}", dst, from, code),
+            Outcome::Timeout if all          => println!("{} timeout", dst),
+            _                                => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse(ports: &str) -> Result<Vec<u16>> {
+    let mut list = Vec::new();
+    for item in ports.split(',') {
+        match item.split_once('-') {
+            Some((lo, hi)) => list.extend(lo.trim().parse::<u16>()?..=hi.trim().parse::<u16>()?),
+            None           => list.push(item.trim().parse()?),
+        }
+    }
+    Ok(list)
+}
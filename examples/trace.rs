@@ -1,29 +1,37 @@
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 use anyhow::{anyhow, Result};
 use futures::{pin_mut, StreamExt};
+use futures::future::join_all;
 use gumdrop::Options;
 use tokio::net::lookup_host;
-use tokio::time::sleep;
-use netdiag::{Bind, Protocol, Tracer, trace::Node};
+use netdiag::{AsTable, Bind, Dns, Names, Node, Protocol, Trace, Tracer};
+use netdiag::icmp::{Extensions, ext::Label};
 
 #[derive(Debug, Options)]
 pub struct Args {
-    #[options()]                help:   bool,
-    #[options(default = "UDP")] proto:  String,
-    #[options()]                port:   u16,
-    #[options(default = "4")]   count:  usize,
-    #[options(default = "30")]  limit:  u8,
-    #[options(default = "500")] delay:  u64,
-    #[options(default = "250")] expiry: u64,
-    #[options(free, required)]  host:   String,
+    #[options()]                help:     bool,
+    #[options(default = "UDP")] proto:    String,
+    #[options()]                port:     u16,
+    #[options(default = "4")]   count:    usize,
+    #[options(default = "30")]  limit:    u8,
+    #[options(default = "500")] interval: u64,
+    #[options()]                deadline: Option<u64>,
+    #[options(default = "250")] expiry:   u64,
+    #[options(no_short)]        paris:    bool,
+    #[options(default = "1")]   window:   usize,
+    #[options()]                gap:      usize,
+    #[options(no_short)]        resolve:  bool,
+    #[options(no_short)]        dns:      Option<SocketAddr>,
+    #[options(no_short)]        asn:      Option<String>,
+    #[options(free, required)]  host:     String,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse_args_default_or_exit();
-    let Args { proto, port, count, limit, delay, expiry, host, .. } = args;
+    let Args { proto, port, count, limit, interval, deadline, expiry, paris, window, gap, resolve, dns, asn, host, .. } = args;
 
     env_logger::init();
 
@@ -34,14 +42,23 @@ async fn main() -> Result<()> {
         _                 => Protocol::default(),
     };
 
-    let delay  = Duration::from_millis(delay);
-    let expiry = Duration::from_millis(expiry);
+    let interval = Duration::from_millis(interval);
+    let deadline = deadline.map(Duration::from_secs);
+    let expiry   = Duration::from_millis(expiry);
 
     let addr = format!("{}:0", host);
     let addr = lookup_host(&addr).await?.next().ok_or_else(|| {
         anyhow!("invalid target")
     })?.ip();
 
+    let names = match (resolve, dns) {
+        (_,     Some(dns)) => Some(Names::new(Dns::new(dns), Duration::from_secs(5))),
+        (true,  None)      => Some(Names::new(Dns::system()?, Duration::from_secs(5))),
+        (false, None)      => None,
+    };
+
+    let table = asn.map(AsTable::open).transpose()?;
+
     println!("tracing {} ({})", host, addr);
 
     let bind = Bind::default();
@@ -49,41 +66,133 @@ async fn main() -> Result<()> {
     let tracer = Tracer::new(&bind).await?;
     let source = tracer.reserve(proto, addr).await?;
 
-    let mut done  = false;
-    let mut ttl   = 1;
-    let mut probe = source.probe()?;
+    let mut probe = match paris {
+        true  => source.paris(0)?,
+        false => source.probe()?,
+    };
 
-    while !done && ttl <= limit {
-        let mut nodes = HashMap::<IpAddr, Vec<String>>::new();
+    if window > 1 {
+        let limit = usize::from(limit);
+        let trace = Trace { proto, addr, probes: count, limit, expiry, interval, deadline, paris, window, gap };
+        let hops  = tracer.parallel(&mut probe, &trace).await?;
+        let hops  = join_all(hops.into_iter().map(|nodes| lookup(names.as_ref(), nodes))).await;
+        let hops  = hops.into_iter().map(|nodes| origin(table.as_ref(), nodes)).collect::<Vec<_>>();
 
-        let stream = tracer.probe(&mut probe, ttl, expiry);
-        let stream = stream.take(count);
-        pin_mut!(stream);
+        for (n, nodes) in hops.iter().enumerate() {
+            hop(nodes.clone(), n as u8 + 1, count, addr);
+        }
 
-        while let Some(Ok(node)) = stream.next().await {
-            if let Node::Node(_, ip, rtt, last) = node {
-                let rtt = format!("{:>0.2?}", rtt);
-                nodes.entry(ip).or_default().push(rtt);
-                done = last || ip == addr;
-            }
+        aspath(table.as_ref(), &hops);
 
-            sleep(delay).await;
-        }
+        return Ok(());
+    }
+
+    let stream = tracer.trace(&mut probe, count, expiry, interval, deadline);
+    let stream = stream.take(usize::from(limit)).map(|result| async {
+        Ok::<_, anyhow::Error>(lookup(names.as_ref(), result?).await)
+    }).buffered(8).enumerate();
+    pin_mut!(stream);
+
+    let mut silent = 0;
+    let mut hops   = Vec::new();
+
+    while let Some((n, Ok(nodes))) = stream.next().await {
+        let nodes = origin(table.as_ref(), nodes);
+
+        silent = match nodes.iter().all(Option::is_none) {
+            true  => silent + 1,
+            false => 0,
+        };
 
-        print(&nodes, ttl, count);
+        hops.push(nodes.clone());
 
-        ttl += 1;
+        if hop(nodes, n as u8 + 1, count, addr) || (gap > 0 && silent >= gap) {
+            break;
+        }
     }
 
+    aspath(table.as_ref(), &hops);
+
     Ok(())
 }
 
-fn print(nodes: &HashMap<IpAddr, Vec<String>>, ttl: u8, probes: usize) {
+async fn lookup(names: Option<&Names>, nodes: Vec<Option<Node>>) -> Vec<Option<Node>> {
+    let names = match names {
+        Some(names) => names,
+        None        => return nodes,
+    };
+
+    join_all(nodes.into_iter().map(|node| async move {
+        Some(names.annotate(node?).await)
+    })).await
+}
+
+fn origin(table: Option<&AsTable>, nodes: Vec<Option<Node>>) -> Vec<Option<Node>> {
+    match table {
+        Some(table) => nodes.into_iter().map(|node| Some(table.annotate(node?))).collect(),
+        None        => nodes,
+    }
+}
+
+fn aspath(table: Option<&AsTable>, hops: &[Vec<Option<Node>>]) {
+    let path = match table {
+        Some(table) => table.path(hops),
+        None        => return,
+    };
+
+    let asns = path.path.iter().map(|asn| format!("AS{}", asn)).collect::<Vec<_>>();
+    println!("AS path: {}", asns.join(" "));
+
+    for crossing in &path.crossings {
+        println!("[{:>3}] AS{} -> AS{} at {}", crossing.ttl, crossing.from, crossing.to, crossing.addr);
+    }
+}
+
+fn hop(nodes: Vec<Option<Node>>, ttl: u8, count: usize, addr: IpAddr) -> bool {
+    let mut rtts = HashMap::<String, Vec<String>>::new();
+    let mut exts = HashMap::<IpAddr, Extensions>::new();
+    let mut done = false;
+
+    let (late, nodes): (Vec<_>, Vec<_>) = nodes.into_iter().flatten().partition(|node| node.late);
+
+    for node in nodes {
+        let rtt = match node.annotation() {
+            Some(note) => format!("{:>0.2?} {}", node.rtt, note),
+            None       => format!("{:>0.2?}", node.rtt),
+        };
+        rtts.entry(host(&node)).or_default().push(rtt);
+        done |= node.last() || node.addr == addr;
+        exts.entry(node.addr).or_insert(node.ext);
+    }
+
+    print(&rtts, ttl, count);
+    extensions(&exts);
+
+    for node in late {
+        println!("[{:>3}] {:32} {:>0.2?} (late)", node.ttl, host(&node), node.rtt);
+    }
+
+    done
+}
+
+fn host(node: &Node) -> String {
+    let host = match &node.name {
+        Some(name) => format!("{} ({})", name, node.addr),
+        None       => node.addr.to_string(),
+    };
+
+    match node.asn {
+        Some(asn) => format!("{} [AS{}]", host, asn),
+        None      => host,
+    }
+}
+
+fn print(nodes: &HashMap<String, Vec<String>>, ttl: u8, probes: usize) {
     let mut count = 0;
 
     let mut output = nodes.iter().map(|(node, rtt)| {
         count += rtt.len();
-        let node = node.to_string();
+        let node = node.clone();
         let rtt  = rtt.join(", ");
         (node, rtt)
     }).collect::<Vec<_>>();
@@ -101,3 +210,19 @@ fn print(nodes: &HashMap<IpAddr, Vec<String>>, ttl: u8, probes: usize) {
         }
     }
 }
+
+fn extensions(exts: &HashMap<IpAddr, Extensions>) {
+    for (ip, ext) in exts {
+        for label in &ext.mpls {
+            let Label { label, tc, bottom, ttl } = label;
+            println!("{:6} {} MPLS label {} tc {} s {} ttl {}", "", ip, label, tc, *bottom as u8, ttl);
+        }
+
+        for interface in &ext.interfaces {
+            let name    = interface.name.as_deref().unwrap_or("-");
+            let ifindex = interface.ifindex.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+            let mtu     = interface.mtu.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+            println!("{:6} {} {:?} interface {} ifindex {} mtu {}", "", ip, interface.role, name, ifindex, mtu);
+        }
+    }
+}
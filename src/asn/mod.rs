@@ -0,0 +1,10 @@
+pub use path::AsHop;
+pub use path::AsPath;
+pub use path::Crossing;
+pub use table::AsTable;
+pub use table::Origin;
+pub use table::Prefix;
+
+mod mrt;
+mod path;
+mod table;
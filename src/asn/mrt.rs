@@ -0,0 +1,320 @@
+use std::convert::{TryFrom, TryInto};
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use anyhow::{anyhow, Result};
+use super::Prefix;
+
+pub fn detect(head: &[u8]) -> bool {
+    match head.get(4..8) {
+        Some(&[0, TABLE_DUMP_V2, 0, subtype]) => (PEER_INDEX_TABLE..=RIB_IPV6_UNICAST_ADDPATH).contains(&subtype),
+        _                                     => false,
+    }
+}
+
+pub fn load<R: Read, F: FnMut(Prefix, u32)>(mut reader: R, mut insert: F) -> Result<usize> {
+    let mut head  = [0u8; HEADER_SIZE];
+    let mut body  = Vec::new();
+    let mut count = 0;
+
+    loop {
+        match reader.read_exact(&mut head) {
+            Ok(())                                          => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e)                                          => return Err(e.into()),
+        }
+
+        let kind    = u16::from_be_bytes([head[4], head[5]]);
+        let subtype = u16::from_be_bytes([head[6], head[7]]);
+        let length  = u32::from_be_bytes(head[8..12].try_into()?);
+
+        body.resize(usize::try_from(length)?, 0);
+        reader.read_exact(&mut body)?;
+
+        if kind != u16::from(TABLE_DUMP_V2) {
+            continue;
+        }
+
+        let rib = match u8::try_from(subtype) {
+            Ok(RIB_IPV4_UNICAST)         => Rib { v6: false, addpath: false },
+            Ok(RIB_IPV6_UNICAST)         => Rib { v6: true,  addpath: false },
+            Ok(RIB_IPV4_UNICAST_ADDPATH) => Rib { v6: false, addpath: true  },
+            Ok(RIB_IPV6_UNICAST_ADDPATH) => Rib { v6: true,  addpath: true  },
+            _                            => continue,
+        };
+
+        if let Some((prefix, asn)) = rib.decode(&body)? {
+            insert(prefix, asn);
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+struct Rib {
+    v6:      bool,
+    addpath: bool,
+}
+
+impl Rib {
+    fn decode(&self, data: &[u8]) -> Result<Option<(Prefix, u32)>> {
+        let mut data = Cursor(data);
+
+        data.take(4)?;
+
+        let len   = data.take(1)?[0];
+        let bytes = data.take(usize::from(len).div_ceil(8))?;
+
+        let addr = match self.v6 {
+            false => IpAddr::V4(Ipv4Addr::from(octets::<4>(bytes))),
+            true  => IpAddr::V6(Ipv6Addr::from(octets::<16>(bytes))),
+        };
+
+        let prefix  = Prefix::new(addr, len)?;
+        let entries = data.u16()?;
+
+        for _ in 0..entries {
+            data.take(6)?;
+
+            if self.addpath {
+                data.take(4)?;
+            }
+
+            let len   = usize::from(data.u16()?);
+            let attrs = data.take(len)?;
+
+            if let Some(asn) = origin(attrs)? {
+                return Ok(Some((prefix, asn)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn origin(attrs: &[u8]) -> Result<Option<u32>> {
+    let mut attrs = Cursor(attrs);
+
+    while !attrs.0.is_empty() {
+        let flags = attrs.take(1)?[0];
+        let kind  = attrs.take(1)?[0];
+        let len   = match flags & EXTENDED {
+            0 => usize::from(attrs.take(1)?[0]),
+            _ => usize::from(attrs.u16()?),
+        };
+        let value = attrs.take(len)?;
+
+        if kind == AS_PATH {
+            return path(value);
+        }
+    }
+
+    Ok(None)
+}
+
+fn path(value: &[u8]) -> Result<Option<u32>> {
+    let mut value = Cursor(value);
+    let mut asn   = None;
+
+    while !value.0.is_empty() {
+        let kind  = value.take(1)?[0];
+        let count = usize::from(value.take(1)?[0]);
+        let asns  = value.take(count * 4)?;
+
+        let mut list = asns.chunks_exact(4).map(|asn| {
+            u32::from_be_bytes([asn[0], asn[1], asn[2], asn[3]])
+        });
+
+        asn = match kind {
+            AS_SEQUENCE => list.next_back(),
+            _           => list.min(),
+        }.or(asn);
+    }
+
+    Ok(asn)
+}
+
+fn octets<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut octets = [0u8; N];
+    let n = bytes.len().min(N);
+    octets[..n].copy_from_slice(&bytes[..n]);
+    octets
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(anyhow!("truncated MRT record"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+const HEADER_SIZE: usize = 12;
+
+const TABLE_DUMP_V2: u8 = 13;
+
+const PEER_INDEX_TABLE:         u8 = 1;
+const RIB_IPV4_UNICAST:         u8 = 2;
+const RIB_IPV6_UNICAST:         u8 = 4;
+const RIB_IPV4_UNICAST_ADDPATH: u8 = 8;
+const RIB_IPV6_UNICAST_ADDPATH: u8 = 10;
+
+const EXTENDED:    u8 = 0x10;
+const AS_PATH:     u8 = 2;
+const AS_SEQUENCE: u8 = 2;
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use super::*;
+
+    #[test]
+    fn detect_header() {
+        assert!(detect(&record(RIB_IPV4_UNICAST, &[])));
+        assert!(detect(&record(PEER_INDEX_TABLE, &[])));
+        assert!(!detect(&record(0, &[])));
+        assert!(!detect(&[0u8; 7]));
+
+        let mut head = record(RIB_IPV4_UNICAST, &[]);
+        head[5] = 12;
+        assert!(!detect(&head));
+    }
+
+    #[test]
+    fn load_four_byte_path() {
+        let attrs = as_path(&[(AS_SEQUENCE, &[64500, 4200000001])], false);
+        let body  = rib(&[192, 0, 2], 24, &[entry(&attrs, false)]);
+
+        assert_eq!(vec![(prefix("192.0.2.0/24"), 4200000001)], load_all(&record(RIB_IPV4_UNICAST, &body)));
+    }
+
+    #[test]
+    fn load_as_set_origin() {
+        let attrs = as_path(&[(AS_SEQUENCE, &[64500]), (AS_SET, &[65002, 65001, 65003])], false);
+        let body  = rib(&[198, 51, 100], 24, &[entry(&attrs, false)]);
+
+        assert_eq!(vec![(prefix("198.51.100.0/24"), 65001)], load_all(&record(RIB_IPV4_UNICAST, &body)));
+
+        let attrs = as_path(&[(AS_SEQUENCE, &[64500]), (AS_SET, &[])], false);
+        let body  = rib(&[198, 51, 100], 24, &[entry(&attrs, false)]);
+
+        assert_eq!(vec![(prefix("198.51.100.0/24"), 64500)], load_all(&record(RIB_IPV4_UNICAST, &body)));
+    }
+
+    #[test]
+    fn load_extended_attrs() {
+        let origin = [0x40, 1, 1, 0];
+        let attrs  = [&origin[..], &as_path(&[(AS_SEQUENCE, &[64500, 64501])], true)].concat();
+        let body   = rib(&[0x20, 0x01, 0x0d, 0xb8], 32, &[entry(&origin, true), entry(&attrs, true)]);
+
+        assert_eq!(vec![(prefix("2001:db8::/32"), 64501)], load_all(&record(RIB_IPV6_UNICAST_ADDPATH, &body)));
+    }
+
+    #[test]
+    fn load_skips_records() {
+        let attrs = as_path(&[(AS_SEQUENCE, &[64500])], false);
+        let body  = rib(&[10], 8, &[entry(&attrs, false)]);
+
+        let mut other = record(RIB_IPV4_UNICAST, &body);
+        other[5] = 12;
+
+        let data = [
+            record(PEER_INDEX_TABLE, &[0u8; 8]),
+            other,
+            record(RIB_IPV4_UNICAST, &rib(&[10], 8, &[])),
+            record(RIB_IPV4_UNICAST, &body),
+        ].concat();
+
+        assert_eq!(vec![(prefix("10.0.0.0/8"), 64500)], load_all(&data));
+    }
+
+    #[test]
+    fn load_truncated() {
+        let attrs = as_path(&[(AS_SEQUENCE, &[64500, 64501])], false);
+        let body  = rib(&[192, 0, 2], 24, &[entry(&attrs, false)]);
+        let data  = record(RIB_IPV4_UNICAST, &body);
+
+        for n in HEADER_SIZE..data.len() {
+            assert!(load(&data[..n], |_, _| ()).is_err(), "{} bytes", n);
+        }
+
+        for n in 0..body.len() {
+            assert!(load(&record(RIB_IPV4_UNICAST, &body[..n])[..], |_, _| ()).is_err(), "{} bytes", n);
+        }
+
+        let mut attrs = as_path(&[(AS_SEQUENCE, &[64500])], false);
+        attrs[4] = 2;
+        let body = rib(&[192, 0, 2], 24, &[entry(&attrs, false)]);
+        assert!(load(&record(RIB_IPV4_UNICAST, &body)[..], |_, _| ()).is_err());
+    }
+
+    #[test]
+    fn load_invalid_prefix() {
+        let attrs = as_path(&[(AS_SEQUENCE, &[64500])], false);
+        let body  = rib(&[192, 0, 2, 1, 0], 33, &[entry(&attrs, false)]);
+        assert!(load(&record(RIB_IPV4_UNICAST, &body)[..], |_, _| ()).is_err());
+    }
+
+    fn load_all(data: &[u8]) -> Vec<(Prefix, u32)> {
+        let mut list = Vec::new();
+        let count = load(data, |prefix, asn| list.push((prefix, asn))).unwrap();
+        assert_eq!(count, list.len());
+        list
+    }
+
+    fn record(subtype: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0, 0, TABLE_DUMP_V2, 0, subtype];
+        data.extend_from_slice(&u32::try_from(body.len()).unwrap().to_be_bytes());
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn rib(bytes: &[u8], len: u8, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1, len];
+        data.extend_from_slice(bytes);
+        data.extend_from_slice(&u16::try_from(entries.len()).unwrap().to_be_bytes());
+        entries.iter().for_each(|entry| data.extend_from_slice(entry));
+        data
+    }
+
+    fn entry(attrs: &[u8], addpath: bool) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0, 0, 0];
+        if addpath {
+            data.extend_from_slice(&[0, 0, 0, 1]);
+        }
+        data.extend_from_slice(&u16::try_from(attrs.len()).unwrap().to_be_bytes());
+        data.extend_from_slice(attrs);
+        data
+    }
+
+    fn as_path(segments: &[(u8, &[u32])], extended: bool) -> Vec<u8> {
+        let mut value = Vec::new();
+        for (kind, asns) in segments {
+            value.extend_from_slice(&[*kind, u8::try_from(asns.len()).unwrap()]);
+            asns.iter().for_each(|asn| value.extend_from_slice(&asn.to_be_bytes()));
+        }
+
+        let mut data = match extended {
+            false => vec![0x40, AS_PATH, u8::try_from(value.len()).unwrap()],
+            true  => [&[0x40 | EXTENDED, AS_PATH][..], &u16::try_from(value.len()).unwrap().to_be_bytes()].concat(),
+        };
+        data.extend_from_slice(&value);
+        data
+    }
+
+    fn prefix(s: &str) -> Prefix {
+        s.parse().unwrap()
+    }
+
+    const AS_SET: u8 = 1;
+}
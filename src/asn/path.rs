@@ -0,0 +1,60 @@
+use std::net::IpAddr;
+use crate::trace::Node;
+use super::{AsTable, Origin};
+
+#[derive(Debug, Default)]
+pub struct AsPath {
+    pub hops:      Vec<AsHop>,
+    pub path:      Vec<u32>,
+    pub crossings: Vec<Crossing>,
+}
+
+#[derive(Debug)]
+pub struct AsHop {
+    pub ttl:    u8,
+    pub addr:   IpAddr,
+    pub origin: Option<Origin>,
+}
+
+#[derive(Debug)]
+pub struct Crossing {
+    pub ttl:  u8,
+    pub from: u32,
+    pub to:   u32,
+    pub addr: IpAddr,
+}
+
+impl AsTable {
+    pub fn path(&self, hops: &[Vec<Option<Node>>]) -> AsPath {
+        let mut path = AsPath::default();
+        let mut last = None;
+
+        for nodes in hops {
+            let node = match nodes.iter().flatten().find(|node| !node.late) {
+                Some(node) => node,
+                None       => continue,
+            };
+
+            let ttl    = node.ttl;
+            let addr   = node.addr;
+            let origin = self.lookup(addr);
+
+            if let Some(Origin { asn, .. }) = origin {
+                match last {
+                    Some(from) if from != asn => path.crossings.push(Crossing { ttl, from, to: asn, addr }),
+                    _                         => (),
+                }
+
+                if last != Some(asn) {
+                    path.path.push(asn);
+                }
+
+                last = Some(asn);
+            }
+
+            path.hops.push(AsHop { ttl, addr, origin });
+        }
+
+        path
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::{anyhow, Error, Result};
+use crate::trace::Node;
+use super::mrt;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Prefix {
+    pub addr: IpAddr,
+    pub len:  u8,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Origin {
+    pub prefix: Prefix,
+    pub asn:    u32,
+}
+
+#[derive(Debug)]
+pub struct AsTable {
+    v4: Vec<HashMap<u128, u32>>,
+    v6: Vec<HashMap<u128, u32>>,
+}
+
+impl AsTable {
+    pub fn new() -> Self {
+        Self {
+            v4: vec![HashMap::new(); usize::from(V4) + 1],
+            v6: vec![HashMap::new(); usize::from(V6) + 1],
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut this = Self::new();
+
+        match mrt::detect(file.fill_buf()?) {
+            true  => this.load_mrt(file)?,
+            false => this.load_tsv(file)?,
+        };
+
+        Ok(this)
+    }
+
+    pub fn load_tsv<R: BufRead>(&mut self, reader: R) -> Result<usize> {
+        let mut count = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            let (prefix, asn) = match fields[..] {
+                [prefix, asn]    => (prefix.parse()?, asn),
+                [addr, len, asn] => (Prefix::new(addr.parse()?, len.parse()?)?, asn),
+                _                => return Err(anyhow!("invalid line: {}", line)),
+            };
+
+            self.insert(prefix, asn.parse::<Asn>()?.0);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn load_mrt<R: Read>(&mut self, reader: R) -> Result<usize> {
+        mrt::load(reader, |prefix, asn| self.insert(prefix, asn))
+    }
+
+    pub fn insert(&mut self, prefix: Prefix, asn: u32) {
+        let len = usize::from(prefix.len);
+        match prefix.addr {
+            IpAddr::V4(addr) => self.v4[len].insert(u32::from(addr).into(), asn),
+            IpAddr::V6(addr) => self.v6[len].insert(u128::from(addr), asn),
+        };
+    }
+
+    pub fn lookup(&self, addr: IpAddr) -> Option<Origin> {
+        let (len, asn) = match addr {
+            IpAddr::V4(addr) => lookup(&self.v4, u32::from(addr).into(), V4)?,
+            IpAddr::V6(addr) => lookup(&self.v6, u128::from(addr), V6)?,
+        };
+        let prefix = Prefix::new(addr, len).ok()?;
+        Some(Origin { prefix, asn })
+    }
+
+    pub fn annotate(&self, mut node: Node) -> Node {
+        node.asn = self.lookup(node.addr).map(|origin| origin.asn);
+        node
+    }
+
+    pub fn len(&self) -> usize {
+        let v4 = self.v4.iter().map(HashMap::len).sum::<usize>();
+        let v6 = self.v6.iter().map(HashMap::len).sum::<usize>();
+        v4 + v6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for AsTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Prefix {
+    pub fn new(addr: IpAddr, len: u8) -> Result<Self> {
+        let bits = match addr {
+            IpAddr::V4(..) => V4,
+            IpAddr::V6(..) => V6,
+        };
+
+        if len > bits {
+            return Err(anyhow!("invalid prefix length: {}", len));
+        }
+
+        let addr = match addr {
+            IpAddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(mask(u32::from(addr).into(), len, V4) as u32)),
+            IpAddr::V6(addr) => IpAddr::V6(Ipv6Addr::from(mask(u128::from(addr), len, V6))),
+        };
+
+        Ok(Self { addr, len })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        Self::new(addr, self.len).ok() == Some(*self)
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, len) = s.split_once('/').ok_or_else(|| anyhow!("invalid prefix: {}", s))?;
+        Self::new(addr.parse()?, len.parse()?)
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+struct Asn(u32);
+
+impl FromStr for Asn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim_start_matches("AS");
+        let s = s.split(['_', ',']).next().unwrap_or(s);
+        Ok(Self(s.parse()?))
+    }
+}
+
+fn lookup(table: &[HashMap<u128, u32>], addr: u128, bits: u8) -> Option<(u8, u32)> {
+    (0..=bits).rev().find_map(|len| {
+        let asn = table[usize::from(len)].get(&mask(addr, len, bits))?;
+        Some((len, *asn))
+    })
+}
+
+fn mask(addr: u128, len: u8, bits: u8) -> u128 {
+    match len.min(bits) {
+        0 => 0,
+        n => addr & (!0u128 >> (128 - u32::from(bits))) & (!0u128 << (bits - n)),
+    }
+}
+
+const V4: u8 = 32;
+const V6: u8 = 128;
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use super::*;
+
+    #[test]
+    fn lookup_default() {
+        let table = build(&[("0.0.0.0/0", 1), ("::/0", 2)]);
+
+        assert_eq!(Some(origin("0.0.0.0/0", 1)), table.lookup(addr("0.0.0.0")));
+        assert_eq!(Some(origin("0.0.0.0/0", 1)), table.lookup(addr("255.255.255.255")));
+        assert_eq!(Some(origin("::/0", 2)),      table.lookup(addr("::")));
+        assert_eq!(Some(origin("::/0", 2)),      table.lookup(addr("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff")));
+
+        let table = build(&[("0.0.0.0/0", 1)]);
+        assert_eq!(None, table.lookup(addr("2001:db8::1")));
+    }
+
+    #[test]
+    fn lookup_host() {
+        let table = build(&[("192.0.2.1/32", 1), ("2001:db8::1/128", 2)]);
+
+        assert_eq!(Some(origin("192.0.2.1/32", 1)),    table.lookup(addr("192.0.2.1")));
+        assert_eq!(Some(origin("2001:db8::1/128", 2)), table.lookup(addr("2001:db8::1")));
+        assert_eq!(None,                               table.lookup(addr("192.0.2.0")));
+        assert_eq!(None,                               table.lookup(addr("192.0.2.2")));
+        assert_eq!(None,                               table.lookup(addr("2001:db8::")));
+        assert_eq!(None,                               table.lookup(addr("2001:db8::2")));
+    }
+
+    #[test]
+    fn lookup_longest() {
+        let table = build(&[
+            ("10.0.0.0/8",    1),
+            ("10.1.0.0/16",   2),
+            ("10.1.2.0/24",   3),
+            ("10.1.2.128/25", 4),
+            ("2001:db8::/32", 5),
+            ("2001:db8::/48", 6),
+        ]);
+
+        assert_eq!(Some(origin("10.0.0.0/8",    1)), table.lookup(addr("10.2.0.1")));
+        assert_eq!(Some(origin("10.1.0.0/16",   2)), table.lookup(addr("10.1.3.1")));
+        assert_eq!(Some(origin("10.1.2.0/24",   3)), table.lookup(addr("10.1.2.127")));
+        assert_eq!(Some(origin("10.1.2.128/25", 4)), table.lookup(addr("10.1.2.128")));
+        assert_eq!(Some(origin("2001:db8::/48", 6)), table.lookup(addr("2001:db8:0:1::1")));
+        assert_eq!(Some(origin("2001:db8::/32", 5)), table.lookup(addr("2001:db8:1::1")));
+        assert_eq!(None,                             table.lookup(addr("11.0.0.1")));
+        assert_eq!(6,                                table.len());
+    }
+
+    #[test]
+    fn insert_replace() {
+        let mut table = build(&[("10.0.0.0/8", 1)]);
+        table.insert(prefix("10.255.0.0/8"), 2);
+
+        assert_eq!(Some(origin("10.0.0.0/8", 2)), table.lookup(addr("10.0.0.1")));
+        assert_eq!(1,                             table.len());
+    }
+
+    #[test]
+    fn prefix_parse() {
+        assert_eq!(Prefix { addr: addr("192.0.2.0"),  len: 24 }, prefix("192.0.2.255/24"));
+        assert_eq!(Prefix { addr: addr("0.0.0.0"),    len: 0  }, prefix("192.0.2.255/0"));
+        assert_eq!(Prefix { addr: addr("2001:db8::"), len: 32 }, prefix("2001:db8:ffff::1/32"));
+        assert_eq!(Prefix { addr: addr("::"),         len: 0  }, prefix("2001:db8::1/0"));
+        assert_eq!("2001:db8::1/128", prefix("2001:db8::1/128").to_string());
+
+        assert!("192.0.2.0/33".parse::<Prefix>().is_err());
+        assert!("2001:db8::/129".parse::<Prefix>().is_err());
+        assert!("192.0.2.0".parse::<Prefix>().is_err());
+
+        assert!(prefix("10.0.0.0/8").contains(addr("10.255.255.255")));
+        assert!(!prefix("10.0.0.0/8").contains(addr("11.0.0.0")));
+        assert!(!prefix("0.0.0.0/0").contains(addr("::")));
+    }
+
+    #[test]
+    fn load_tsv_formats() {
+        let data = "# comment\n\n192.0.2.0/24\t64500\n198.51.100.0 24 AS64501\n2001:db8::/32 64502_64503\n";
+
+        let mut table = AsTable::new();
+        assert_eq!(3, table.load_tsv(data.as_bytes()).unwrap());
+        assert_eq!(Some(64500), table.lookup(addr("192.0.2.1")).map(|o| o.asn));
+        assert_eq!(Some(64501), table.lookup(addr("198.51.100.1")).map(|o| o.asn));
+        assert_eq!(Some(64502), table.lookup(addr("2001:db8::1")).map(|o| o.asn));
+
+        assert!(AsTable::new().load_tsv("192.0.2.0/24".as_bytes()).is_err());
+        assert!(AsTable::new().load_tsv("192.0.2.0/24 AS".as_bytes()).is_err());
+    }
+
+    fn build(entries: &[(&str, u32)]) -> AsTable {
+        let mut table = AsTable::new();
+        for (prefix, asn) in entries {
+            table.insert(prefix.parse().unwrap(), *asn);
+        }
+        table
+    }
+
+    fn origin(prefix: &str, asn: u32) -> Origin {
+        Origin { prefix: prefix.parse().unwrap(), asn }
+    }
+
+    fn prefix(s: &str) -> Prefix {
+        s.parse().unwrap()
+    }
+
+    fn addr(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use libc::{c_long, time_t};
+use parking_lot::Mutex;
+use raw_socket::control::Raw;
+use raw_socket::option::Opt;
+use raw_socket::tokio::prelude::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Clock {
+    pub send: Timestamp,
+    pub recv: Timestamp,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Timestamp {
+    User,
+    Kernel,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sent {
+    user:   Instant,
+    kernel: Arc<Mutex<Option<Instant>>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Transmit {
+    queue: Mutex<Queue>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    next: u32,
+    sent: VecDeque<(u32, Sent)>,
+}
+
+pub trait Sockopt {
+    fn set_sockopt<O: Opt>(&self, level: Level, name: Name, value: &O) -> std::io::Result<()>;
+}
+
+impl Sockopt for RawSocket {
+    fn set_sockopt<O: Opt>(&self, level: Level, name: Name, value: &O) -> std::io::Result<()> {
+        RawSocket::set_sockopt(self, level, name, value)
+    }
+}
+
+impl<S: Sockopt> Sockopt for Arc<S> {
+    fn set_sockopt<O: Opt>(&self, level: Level, name: Name, value: &O) -> std::io::Result<()> {
+        S::set_sockopt(self, level, name, value)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn enable<S: Sockopt>(sock: &S) -> Result<()> {
+    let enable: libc::c_int = 1;
+    Ok(sock.set_sockopt(Level::SOCKET, Name::from(libc::SO_TIMESTAMPNS), &enable)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enable<S: Sockopt>(_sock: &S) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn transmit<S: Sockopt>(sock: &S, tx: &Transmit) -> Result<()> {
+    let flags = libc::SOF_TIMESTAMPING_TX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_SOFTWARE
+        | libc::SOF_TIMESTAMPING_OPT_ID
+        | libc::SOF_TIMESTAMPING_OPT_TSONLY;
+    let flags = flags as libc::c_int;
+    let reset: libc::c_int = 0;
+
+    sock.set_sockopt(Level::SOCKET, Name::from(libc::SO_TIMESTAMPING), &reset)?;
+    sock.set_sockopt(Level::SOCKET, Name::from(libc::SO_TIMESTAMPING), &flags)?;
+    tx.reset();
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn transmit<S: Sockopt>(_sock: &S, tx: &Transmit) -> Result<()> {
+    tx.reset();
+    Ok(())
+}
+
+pub fn transmitted(ctl: &[u8]) -> Option<Instant> {
+    let when = CMsg::decode(ctl).find_map(|msg| {
+        match msg {
+            CMsg::Raw(Raw { level: SOL_SOCKET, kind: SCM_TIMESTAMPING, data }) => timespec(data),
+            _                                                                 => None,
+        }
+    })?;
+    instant(when)
+}
+
+pub fn stamp(ctl: &[u8]) -> (Instant, Clock) {
+    let when = CMsg::decode(ctl).find_map(|msg| {
+        match msg {
+            CMsg::Raw(Raw { level: SOL_SOCKET, kind: SCM_TIMESTAMPNS, data }) => timespec(data),
+            _                                                                => None,
+        }
+    });
+
+    let (when, recv) = match when.and_then(instant) {
+        Some(when) => (when, Timestamp::Kernel),
+        None       => (Instant::now(), Timestamp::User),
+    };
+
+    (when, Clock { send: Timestamp::User, recv })
+}
+
+impl Sent {
+    pub fn when(&self) -> (Instant, Timestamp) {
+        match *self.kernel.lock() {
+            Some(when) => (when, Timestamp::Kernel),
+            None       => (self.user, Timestamp::User),
+        }
+    }
+}
+
+impl Transmit {
+    pub fn sent(&self) -> Sent {
+        let mut queue = self.queue.lock();
+
+        let id   = queue.next;
+        let sent = Sent { user: Instant::now(), kernel: Default::default() };
+
+        queue.next = id.wrapping_add(1);
+        queue.sent.push_back((id, sent.clone()));
+
+        if queue.sent.len() > TRANSMIT_MAX {
+            queue.sent.pop_front();
+        }
+
+        sent
+    }
+
+    pub fn stamp(&self, id: u32, when: Instant) {
+        let queue = self.queue.lock();
+        if let Some((_, sent)) = queue.sent.iter().rev().find(|(n, _)| *n == id) {
+            *sent.kernel.lock() = Some(when);
+        }
+    }
+
+    fn reset(&self) {
+        let mut queue = self.queue.lock();
+        queue.next = 0;
+        queue.sent.clear();
+    }
+}
+
+fn instant(when: SystemTime) -> Option<Instant> {
+    let now = Instant::now();
+    let age = SystemTime::now().duration_since(when).ok()?;
+    Some(now.checked_sub(age).unwrap_or(now))
+}
+
+fn timespec(data: &[u8]) -> Option<SystemTime> {
+    let (sec, nsec) = data.split_at(size_of::<time_t>().min(data.len()));
+    let sec  = time_t::from_ne_bytes(sec.try_into().ok()?);
+    let nsec = c_long::from_ne_bytes(nsec.get(..size_of::<c_long>())?.try_into().ok()?);
+    let when = Duration::new(sec.try_into().ok()?, nsec.try_into().ok()?);
+    Some(UNIX_EPOCH + when)
+}
+
+#[cfg(target_os = "linux")]
+const SCM_TIMESTAMPNS: libc::c_int = libc::SCM_TIMESTAMPNS;
+
+#[cfg(target_os = "linux")]
+const SCM_TIMESTAMPING: libc::c_int = libc::SCM_TIMESTAMPING;
+
+#[cfg(not(target_os = "linux"))]
+const SCM_TIMESTAMPNS: libc::c_int = -1;
+
+#[cfg(not(target_os = "linux"))]
+const SCM_TIMESTAMPING: libc::c_int = -2;
+
+const SOL_SOCKET: libc::c_int = libc::SOL_SOCKET;
+
+const TRANSMIT_MAX: usize = 4096;
@@ -0,0 +1,327 @@
+use std::convert::{TryFrom, TryInto};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use anyhow::{anyhow, Error};
+use super::icmp4::checksum;
+
+pub const VERSION:     u8    = 2;
+pub const HEADER_SIZE: usize = 4;
+pub const ORIGINAL:    usize = 128;
+
+pub const MPLS:      u8 = 1;
+pub const INTERFACE: u8 = 2;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Extensions {
+    pub mpls:       Vec<Label>,
+    pub interfaces: Vec<Interface>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Label {
+    pub label:  u32,
+    pub tc:     u8,
+    pub bottom: bool,
+    pub ttl:    u8,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Interface {
+    pub role:    Role,
+    pub ifindex: Option<u32>,
+    pub addr:    Option<IpAddr>,
+    pub name:    Option<String>,
+    pub mtu:     Option<u32>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Role {
+    Incoming,
+    SubIp,
+    Outgoing,
+    NextHop,
+}
+
+impl Extensions {
+    pub fn decode(data: &[u8], length: usize) -> Option<Self> {
+        let offset = match length {
+            0 if data.len() > ORIGINAL => ORIGINAL,
+            0                          => return None,
+            n                          => n.max(ORIGINAL),
+        };
+
+        let ext = data.get(offset..)?;
+
+        if ext.len() < HEADER_SIZE || ext[0] >> 4 != VERSION {
+            return None;
+        }
+
+        if ext[2..4] != [0, 0] && checksum(ext) != 0 {
+            return None;
+        }
+
+        let mut this = Self::default();
+        let mut rest = &ext[HEADER_SIZE..];
+
+        while rest.len() >= HEADER_SIZE {
+            let len  = usize::from(u16::from_be_bytes([rest[0], rest[1]]));
+            let obj  = rest.get(HEADER_SIZE..len)?;
+            let kind = rest[3];
+
+            match rest[2] {
+                MPLS      => this.mpls.extend(obj.chunks_exact(4).map(Label::from)),
+                INTERFACE => this.interfaces.extend(Interface::try_from((kind, obj)).ok()),
+                _         => (),
+            }
+
+            rest = &rest[len..];
+        }
+
+        Some(this)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mpls.is_empty() && self.interfaces.is_empty()
+    }
+}
+
+impl From<&[u8]> for Label {
+    fn from(slice: &[u8]) -> Self {
+        let entry = u32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]]);
+        Self {
+            label:  entry >> 12,
+            tc:     (entry >> 9) as u8 & 0x07,
+            bottom: entry & 0x100 != 0,
+            ttl:    entry as u8,
+        }
+    }
+}
+
+impl TryFrom<(u8, &[u8])> for Interface {
+    type Error = Error;
+
+    fn try_from((kind, mut slice): (u8, &[u8])) -> Result<Self, Self::Error> {
+        let role = match kind >> 6 {
+            0 => Role::Incoming,
+            1 => Role::SubIp,
+            2 => Role::Outgoing,
+            _ => Role::NextHop,
+        };
+
+        let mut this = Self { role, ifindex: None, addr: None, name: None, mtu: None };
+
+        if kind & IFINDEX != 0 {
+            this.ifindex = Some(u32::from_be_bytes(take(&mut slice, 4)?.try_into()?));
+        }
+
+        if kind & ADDR != 0 {
+            let afi = u16::from_be_bytes(take(&mut slice, 4)?[0..2].try_into()?);
+            this.addr = Some(match afi {
+                AFI4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(take(&mut slice, 4)?)?)),
+                AFI6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(take(&mut slice, 16)?)?)),
+                afi  => return Err(anyhow!("unsupported AFI: {}", afi)),
+            });
+        }
+
+        if kind & NAME != 0 {
+            let len  = usize::from(*slice.first().ok_or_else(short)?);
+            let name = take(&mut slice, len)?.get(1..).ok_or_else(short)?;
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            this.name = Some(String::from_utf8_lossy(name).into_owned());
+        }
+
+        if kind & MTU != 0 {
+            this.mtu = Some(u32::from_be_bytes(take(&mut slice, 4)?.try_into()?));
+        }
+
+        Ok(this)
+    }
+}
+
+fn take<'a>(slice: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if slice.len() < n {
+        return Err(short());
+    }
+    let (head, tail) = slice.split_at(n);
+    *slice = tail;
+    Ok(head)
+}
+
+fn short() -> Error {
+    anyhow!("short interface information object")
+}
+
+const IFINDEX: u8 = 0x08;
+const ADDR:    u8 = 0x04;
+const NAME:    u8 = 0x02;
+const MTU:     u8 = 0x01;
+
+const AFI4: u16 = 1;
+const AFI6: u16 = 2;
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use std::net::IpAddr;
+    use super::*;
+
+    #[test]
+    fn decode_offset() {
+        let ext = extension(&[(MPLS, 1, &label(16, false, 1))]);
+
+        let data = [&[0u8; ORIGINAL][..], &ext].concat();
+        assert_eq!(Some(1), Extensions::decode(&data, 0).map(|e| e.mpls.len()));
+        assert_eq!(Some(1), Extensions::decode(&data, 64).map(|e| e.mpls.len()));
+        assert_eq!(Some(1), Extensions::decode(&data, ORIGINAL).map(|e| e.mpls.len()));
+
+        let data = [&[0u8; 136][..], &ext].concat();
+        assert_eq!(Some(1), Extensions::decode(&data, 136).map(|e| e.mpls.len()));
+        assert_eq!(None,    Extensions::decode(&data, 0));
+
+        let data = [0u8; ORIGINAL];
+        assert_eq!(None, Extensions::decode(&data, 0));
+        assert_eq!(None, Extensions::decode(&data, ORIGINAL));
+    }
+
+    #[test]
+    fn decode_version() {
+        let mut ext = extension(&[]);
+        ext[0] = 1 << 4;
+        assert_eq!(None, decode(&ext));
+    }
+
+    #[test]
+    fn decode_checksum() {
+        let mut ext = extension(&[(MPLS, 1, &label(16, false, 1))]);
+        ext[2..4].copy_from_slice(&[0, 0]);
+        assert!(decode(&ext).is_some());
+
+        ext[2..4].copy_from_slice(&[0xde, 0xad]);
+        assert_eq!(None, decode(&ext));
+    }
+
+    #[test]
+    fn decode_mpls() {
+        let data = [label(16, false, 255), label(1048575, true, 1)].concat();
+        let ext  = decode(&extension(&[(MPLS, 1, &data)])).unwrap();
+
+        assert_eq!(vec![
+            Label { label: 16,      tc: 5, bottom: false, ttl: 255 },
+            Label { label: 1048575, tc: 5, bottom: true,  ttl: 1   },
+        ], ext.mpls);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let mut ext = extension(&[(MPLS, 1, &label(16, false, 1))]);
+        ext.truncate(ext.len() - 2);
+        fix(&mut ext);
+        assert_eq!(None, decode(&ext));
+
+        let mut ext = extension(&[(MPLS, 1, &label(16, false, 1))]);
+        ext[4..6].copy_from_slice(&12u16.to_be_bytes());
+        fix(&mut ext);
+        assert_eq!(None, decode(&ext));
+    }
+
+    #[test]
+    fn decode_zero_length() {
+        for len in 0..HEADER_SIZE as u16 {
+            let mut ext = extension(&[(MPLS, 1, &label(16, false, 1))]);
+            ext[4..6].copy_from_slice(&len.to_be_bytes());
+            fix(&mut ext);
+            assert_eq!(None, decode(&ext));
+        }
+    }
+
+    #[test]
+    fn decode_unknown() {
+        let ext = extension(&[(9, 1, &[1, 2, 3, 4]), (MPLS, 1, &label(16, false, 1))]);
+        assert_eq!(Some(1), decode(&ext).map(|e| e.mpls.len()));
+    }
+
+    #[test]
+    fn interface_full() {
+        let kind = 2 << 6 | IFINDEX | ADDR | NAME | MTU;
+        let data = [
+            &7u32.to_be_bytes()[..],
+            &[0, 1, 0, 0, 192, 0, 2, 1],
+            &[8, b'e', b't', b'h', b'0', 0, 0, 0],
+            &1500u32.to_be_bytes(),
+        ].concat();
+
+        let ext = decode(&extension(&[(INTERFACE, kind, &data)])).unwrap();
+
+        assert_eq!(vec![Interface {
+            role:    Role::Outgoing,
+            ifindex: Some(7),
+            addr:    Some(IpAddr::from([192, 0, 2, 1])),
+            name:    Some("eth0".to_owned()),
+            mtu:     Some(1500),
+        }], ext.interfaces);
+    }
+
+    #[test]
+    fn interface_minimal() {
+        let addr = [&[0, 2, 0, 0][..], &[0x20, 0x01, 0x0d, 0xb8], &[0; 11], &[1]].concat();
+
+        let iface = Interface::try_from((ADDR, &addr[..])).unwrap();
+        assert_eq!(Role::Incoming, iface.role);
+        assert_eq!(None,           iface.ifindex);
+        assert_eq!(Some(IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1])), iface.addr);
+        assert_eq!(None,           iface.name);
+        assert_eq!(None,           iface.mtu);
+
+        let iface = Interface::try_from((1 << 6 | IFINDEX, &3u32.to_be_bytes()[..])).unwrap();
+        assert_eq!(Role::SubIp, iface.role);
+        assert_eq!(Some(3),     iface.ifindex);
+        assert_eq!(None,        iface.name);
+        assert_eq!(None,        iface.mtu);
+
+        let iface = Interface::try_from((3 << 6 | MTU, &9000u32.to_be_bytes()[..])).unwrap();
+        assert_eq!(Role::NextHop, iface.role);
+        assert_eq!(None,          iface.name);
+        assert_eq!(Some(9000),    iface.mtu);
+    }
+
+    #[test]
+    fn interface_truncated() {
+        assert!(Interface::try_from((IFINDEX, &[0, 0, 1][..])).is_err());
+        assert!(Interface::try_from((ADDR,    &[0, 1, 0, 0, 192, 0][..])).is_err());
+        assert!(Interface::try_from((ADDR,    &[0, 3, 0, 0, 192, 0, 2, 1][..])).is_err());
+        assert!(Interface::try_from((NAME,    &[][..])).is_err());
+        assert!(Interface::try_from((NAME,    &[8, b'e', b't'][..])).is_err());
+        assert!(Interface::try_from((NAME,    &[0][..])).is_err());
+        assert!(Interface::try_from((MTU,     &[0, 0][..])).is_err());
+
+        let data = [&[0, 1, 0, 0, 192, 0, 2, 1][..], &[0, 0]].concat();
+        let ext  = decode(&extension(&[(INTERFACE, ADDR | MTU, &data)])).unwrap();
+        assert!(ext.interfaces.is_empty());
+    }
+
+    fn decode(ext: &[u8]) -> Option<Extensions> {
+        let data = [&[0u8; ORIGINAL][..], ext].concat();
+        Extensions::decode(&data, ORIGINAL)
+    }
+
+    fn extension(objects: &[(u8, u8, &[u8])]) -> Vec<u8> {
+        let mut ext = vec![VERSION << 4, 0, 0, 0];
+        for (class, kind, data) in objects {
+            let len = u16::try_from(HEADER_SIZE + data.len()).unwrap();
+            ext.extend_from_slice(&len.to_be_bytes());
+            ext.extend_from_slice(&[*class, *kind]);
+            ext.extend_from_slice(data);
+        }
+        fix(&mut ext);
+        ext
+    }
+
+    fn fix(ext: &mut [u8]) {
+        ext[2..4].copy_from_slice(&[0, 0]);
+        let sum = checksum(ext);
+        ext[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+
+    fn label(label: u32, bottom: bool, ttl: u8) -> [u8; 4] {
+        (label << 12 | 5 << 9 | u32::from(bottom) << 8 | u32::from(ttl)).to_be_bytes()
+    }
+}
@@ -1,6 +1,7 @@
 use std::convert::{TryFrom, TryInto};
 use anyhow::{anyhow, Error};
 use super::echo::Echo;
+use super::ext::Extensions;
 
 pub const HEADER_SIZE: usize = 8;
 
@@ -24,6 +25,7 @@ pub enum Unreachable<'a> {
     Host(&'a [u8]),
     Protocol(&'a [u8]),
     Port(&'a [u8]),
+    Fragmentation(u16, &'a [u8]),
     Other(u8, &'a [u8]),
 }
 
@@ -60,11 +62,35 @@ impl<'a> TryFrom<(u8, &'a [u8])> for Unreachable<'a> {
             1 => Unreachable::Host(data),
             2 => Unreachable::Protocol(data),
             3 => Unreachable::Port(data),
+            4 => Unreachable::Fragmentation(mtu(slice)?, data),
             c => Unreachable::Other(c, data),
         })
     }
 }
 
+impl Unreachable<'_> {
+    pub fn mtu(&self) -> Option<u16> {
+        match self {
+            Unreachable::Fragmentation(mtu, _) => Some(*mtu),
+            _                                  => None,
+        }
+    }
+}
+
+pub fn extensions(slice: &[u8]) -> Option<Extensions> {
+    match *slice.first()? {
+        UNREACHABLE | TIME_EXCEEDED => (),
+        _                           => return None,
+    }
+
+    let length = usize::from(*slice.get(5)?) * 4;
+    Extensions::decode(slice.get(HEADER_SIZE..)?, length)
+}
+
+fn mtu(slice: &[u8]) -> Result<u16, Error> {
+    Ok(u16::from_be_bytes(slice[2..4].try_into()?))
+}
+
 pub fn checksum(pkt: &[u8]) -> u16 {
     let mut sum = 0u32;
 
@@ -1,10 +1,12 @@
 use std::convert::{TryFrom, TryInto};
 use anyhow::{anyhow, Error};
 use super::echo::Echo;
+use super::ext::Extensions;
 
 pub const HEADER_SIZE: usize = 8;
 
 pub const UNREACHABLE:   u8 = 1;
+pub const TOO_BIG:       u8 = 2;
 pub const TIME_EXCEEDED: u8 = 3;
 pub const ECHO_REQUEST:  u8 = 128;
 pub const ECHO_REPLY:    u8 = 129;
@@ -12,6 +14,7 @@ pub const ECHO_REPLY:    u8 = 129;
 #[derive(Debug)]
 pub enum IcmpV6Packet<'a> {
     Unreachable(Unreachable<'a>),
+    PacketTooBig(u32, &'a [u8]),
     EchoRequest(Echo<'a>),
     EchoReply(Echo<'a>),
     HopLimitExceeded(&'a [u8]),
@@ -40,6 +43,7 @@ impl<'a> TryFrom<&'a [u8]> for IcmpV6Packet<'a> {
 
         Ok(match (kind, code) {
             (UNREACHABLE,   _) => IcmpV6Packet::Unreachable((code, rest).try_into()?),
+            (TOO_BIG,       0) => IcmpV6Packet::PacketTooBig(mtu(rest)?, &rest[4..]),
             (TIME_EXCEEDED, 0) => IcmpV6Packet::HopLimitExceeded(&rest[4..]),
             (TIME_EXCEEDED, 1) => IcmpV6Packet::ReassemblyTimeExceeded(&rest[4..]),
             (ECHO_REQUEST,  0) => IcmpV6Packet::EchoRequest(rest.try_into()?),
@@ -61,3 +65,17 @@ impl<'a> TryFrom<(u8, &'a [u8])> for Unreachable<'a> {
         })
     }
 }
+
+pub fn extensions(slice: &[u8]) -> Option<Extensions> {
+    match *slice.first()? {
+        UNREACHABLE | TIME_EXCEEDED => (),
+        _                           => return None,
+    }
+
+    let length = usize::from(*slice.get(4)?) * 8;
+    Extensions::decode(slice.get(HEADER_SIZE..)?, length)
+}
+
+fn mtu(slice: &[u8]) -> Result<u32, Error> {
+    Ok(u32::from_be_bytes(slice[0..4].try_into()?))
+}
@@ -1,6 +1,8 @@
+pub use ext::Extensions;
 pub use icmp4::IcmpV4Packet;
 pub use icmp6::IcmpV6Packet;
 
+pub mod ext;
 pub mod icmp4;
 pub mod icmp6;
 
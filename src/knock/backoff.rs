@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Backoff {
+    #[default]
+    Fixed,
+    Exponential,
+    Custom(Vec<Duration>),
+}
+
+impl Backoff {
+    pub fn wait(&self, expiry: Duration, attempt: usize) -> Duration {
+        match self {
+            Self::Fixed        => expiry,
+            Self::Exponential  => expiry.saturating_mul(1 << attempt.min(16)),
+            Self::Custom(list) => list.get(attempt).or_else(|| list.last()).copied().unwrap_or(expiry),
+        }
+    }
+
+    pub fn schedule(&self, expiry: Duration, retries: usize) -> Vec<Duration> {
+        (0..=retries).map(|n| self.wait(expiry, n)).collect()
+    }
+}
@@ -0,0 +1,166 @@
+use etherparse::TcpHeader;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Handshake {
+    pub ttl:       Option<u8>,
+    pub window:    u16,
+    pub mss:       Option<u16>,
+    pub scale:     Option<u8>,
+    pub sack:      bool,
+    pub timestamp: Option<(u32, u32)>,
+    pub options:   Vec<u8>,
+}
+
+impl Handshake {
+    pub fn new(head: &TcpHeader, ttl: Option<u8>) -> Self {
+        let mut this = Self { ttl, window: head.window_size, ..Default::default() };
+        let mut opts = head.options();
+
+        while let Some(&kind) = opts.first() {
+            this.options.push(kind);
+
+            let len = match (kind, opts.get(1)) {
+                (EOL, _)                   => break,
+                (NOP, _)                   => 1,
+                (_, Some(&len)) if len > 1 => usize::from(len),
+                _                          => break,
+            };
+
+            let data = match opts.get(..len) {
+                Some(data) => data,
+                None       => break,
+            };
+
+            match (kind, data) {
+                (MSS,     &[_, _, a, b])                   => this.mss       = Some(u16::from_be_bytes([a, b])),
+                (WS,      &[_, _, n])                      => this.scale     = Some(n),
+                (SACK_OK, &[_, _])                         => this.sack      = true,
+                (TS,      &[_, _, a, b, c, d, e, f, g, h]) => this.timestamp = Some((
+                    u32::from_be_bytes([a, b, c, d]),
+                    u32::from_be_bytes([e, f, g, h]),
+                )),
+                _                                          => (),
+            }
+
+            opts = &opts[len..];
+        }
+
+        this
+    }
+
+    pub fn initial_ttl(&self) -> Option<u8> {
+        let ttl = self.ttl?;
+        Some([32, 64, 128, 255].iter().copied().find(|&n| ttl <= n).unwrap_or(255))
+    }
+
+    pub fn fingerprint(&self) -> String {
+        let field = |value: Option<String>| value.unwrap_or_else(|| "*".to_owned());
+        let ttl   = self.initial_ttl().map(|n| n.to_string());
+        let mss   = self.mss.map(|n| n.to_string());
+        let scale = self.scale.map(|n| n.to_string());
+
+        let layout = self.options.iter().map(|kind| {
+            match *kind {
+                EOL     => "eol".to_owned(),
+                NOP     => "nop".to_owned(),
+                MSS     => "mss".to_owned(),
+                WS      => "ws".to_owned(),
+                SACK_OK => "sok".to_owned(),
+                SACK    => "sack".to_owned(),
+                TS      => "ts".to_owned(),
+                kind    => format!("?{}", kind),
+            }
+        }).collect::<Vec<_>>().join(",");
+
+        format!("{}:{}:{}:{}:{}", field(ttl), self.window, field(mss), field(scale), layout)
+    }
+}
+
+const EOL:     u8 = 0;
+const NOP:     u8 = 1;
+const MSS:     u8 = 2;
+const WS:      u8 = 3;
+const SACK_OK: u8 = 4;
+const SACK:    u8 = 5;
+const TS:      u8 = 8;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_options() {
+        let shake = handshake(&[
+            MSS, 4, 0x05, 0xb4,
+            SACK_OK, 2,
+            TS, 10, 0, 0, 0, 1, 0, 0, 0, 2,
+            NOP,
+            WS, 3, 7,
+        ]);
+
+        assert!(shake.sack);
+        assert_eq!(Some(1460),   shake.mss);
+        assert_eq!(Some(7),      shake.scale);
+        assert_eq!(Some((1, 2)), shake.timestamp);
+        assert_eq!(vec![MSS, SACK_OK, TS, NOP, WS], shake.options);
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let shake = handshake(&[NOP, NOP, MSS, 4, 0x05, 0xb4, TS, 10, 0, 0, 0, 1]);
+        assert_eq!(Some(1460), shake.mss);
+        assert_eq!(None,       shake.timestamp);
+        assert_eq!(vec![NOP, NOP, MSS, TS], shake.options);
+
+        let shake = handshake(&[NOP, NOP, NOP, WS]);
+        assert_eq!(None, shake.scale);
+        assert_eq!(vec![NOP, NOP, NOP, WS], shake.options);
+
+        let shake = handshake(&[MSS, 3, 0x05, 0xb4]);
+        assert_eq!(None, shake.mss);
+        assert_eq!(vec![MSS, 0xb4], shake.options);
+    }
+
+    #[test]
+    fn parse_short_lengths() {
+        let shake = handshake(&[MSS, 0, 0x05, 0xb4, SACK_OK, 2]);
+        assert!(!shake.sack);
+        assert_eq!(None, shake.mss);
+        assert_eq!(vec![MSS], shake.options);
+
+        let shake = handshake(&[WS, 1, 7, NOP, SACK_OK, 2]);
+        assert!(!shake.sack);
+        assert_eq!(None, shake.scale);
+        assert_eq!(vec![WS], shake.options);
+    }
+
+    #[test]
+    fn parse_eol_nop() {
+        let shake = handshake(&[NOP, NOP, EOL, MSS, 4, 0x05, 0xb4]);
+        assert_eq!(None, shake.mss);
+        assert_eq!(vec![NOP, NOP, EOL], shake.options);
+
+        let shake = handshake(&[NOP, WS, 3, 2]);
+        assert_eq!(Some(2), shake.scale);
+        assert_eq!(vec![NOP, WS], shake.options);
+    }
+
+    #[test]
+    fn fingerprint() {
+        let mut head = TcpHeader::new(80, 40000, 0, 64240);
+        head.set_options_raw(&[MSS, 4, 0x05, 0xb4, NOP, NOP, SACK_OK, 2, NOP, WS, 3, 7, 30, 2]).unwrap();
+
+        let shake = Handshake::new(&head, Some(57));
+        assert_eq!(Some(64), shake.initial_ttl());
+        assert_eq!("64:64240:1460:7:mss,nop,nop,sok,nop,ws,?30,eol", shake.fingerprint());
+
+        let shake = Handshake::new(&TcpHeader::new(80, 40000, 0, 1024), None);
+        assert_eq!("*:1024:*:*:", shake.fingerprint());
+    }
+
+    fn handshake(options: &[u8]) -> Handshake {
+        let mut head = TcpHeader::new(80, 40000, 0, 65535);
+        head.set_options_raw(options).unwrap();
+        Handshake::new(&head, Some(64))
+    }
+}
@@ -3,20 +3,45 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use anyhow::Result;
 use futures::{Stream, StreamExt};
-use futures::stream::try_unfold;
+use futures::stream;
 use rand::prelude::*;
-use tokio::time::timeout;
-use crate::Bind;
-use super::{probe::Probe, reply::Reply};
+use tokio::time::timeout_at;
+use crate::{Bind, Limiter};
+use crate::limit::ticks;
+use super::backoff::Backoff;
+use super::handshake::Handshake;
+use super::outcome::{Outcome, Response};
+use super::probe::Probe;
+use super::reply::{Kind, Reply};
 use super::{sock4::Sock4, sock6::Sock6};
 use super::state::{Lease, State};
 
 #[derive(Debug)]
 pub struct Knock {
-    pub addr:   IpAddr,
-    pub port:   u16,
-    pub count:  usize,
-    pub expiry: Duration,
+    pub addr:     IpAddr,
+    pub port:     u16,
+    pub count:    usize,
+    pub expiry:   Duration,
+    pub interval: Duration,
+    pub deadline: Option<Duration>,
+    pub retries:  usize,
+    pub backoff:  Backoff,
+    pub reset:    bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Scan {
+    pub expiry:   Duration,
+    pub retries:  usize,
+    pub backoff:  Backoff,
+    pub reset:    bool,
+    pub parallel: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub addr:  IpAddr,
+    pub ports: Vec<u16>,
 }
 
 pub struct Knocker {
@@ -25,6 +50,18 @@ pub struct Knocker {
     state: Arc<State>,
 }
 
+impl Default for Scan {
+    fn default() -> Self {
+        Self {
+            expiry:   Duration::from_secs(1),
+            retries:  0,
+            backoff:  Backoff::Fixed,
+            reset:    false,
+            parallel: 64,
+        }
+    }
+}
+
 impl Knocker {
     pub async fn new(bind: &Bind) -> Result<Self> {
         let state = Arc::new(State::new());
@@ -35,40 +72,104 @@ impl Knocker {
         Ok(Self { sock4, sock6, state })
     }
 
-    pub async fn knock(&self, knock: &Knock) -> Result<impl Stream<Item = Result<Option<Duration>>> + '_> {
-        let Knock { addr, port, count, expiry } = *knock;
+    pub async fn knock(&self, knock: &Knock) -> Result<impl Stream<Item = Result<Response>> + '_> {
+        let Knock { addr, port, count, expiry, interval, deadline, retries, reset, .. } = *knock;
+
+        let dst   = SocketAddr::new(addr, port);
+        let src   = self.source(addr, port).await?;
+        let waits = knock.backoff.schedule(expiry, retries);
+
+        Ok(ticks(interval, deadline).take(count).map(move |_| {
+            let waits = waits.clone();
+            async move {
+                self.exec(src, dst, &waits, reset, None).await
+            }
+        }).buffered(count.max(1)))
+    }
+
+    pub async fn scan<'a>(
+        &'a self,
+        targets: &'a [Target],
+        scan:    &Scan,
+        limiter: &'a Limiter,
+    ) -> Result<impl Stream<Item = (SocketAddr, Result<Response>)> + 'a> {
+        let Scan { expiry, retries, reset, parallel, .. } = *scan;
+
+        let waits = scan.backoff.schedule(expiry, retries);
+        let mut hosts = Vec::new();
+
+        for Target { addr, ports } in targets {
+            if let Some(&port) = ports.first() {
+                let src = self.source(*addr, port).await?;
+                hosts.push((src, *addr, ports));
+            }
+        }
+
+        let probes = stream::iter(hosts).flat_map(|(src, addr, ports)| {
+            stream::iter(ports.iter().map(move |&port| (src, SocketAddr::new(addr, port))))
+        });
 
-        let dst = SocketAddr::new(addr, port);
-        let src = self.source(addr, port).await?;
+        Ok(probes.map(move |(src, dst)| {
+            let waits = waits.clone();
+            async move {
+                (dst, self.exec(src, dst, &waits, reset, Some(limiter)).await)
+            }
+        }).buffer_unordered(parallel.max(1)))
+    }
 
-        Ok(try_unfold((), move |()| async move {
-            let lease = self.state.reserve(src, dst).await;
-            let seq   = random();
-            let probe = Probe::new(lease.src(), dst, seq)?;
-            let rtt   = self.probe(&probe, lease, expiry).await?;
-            Ok(Some((rtt, ())))
-        }).take(count))
+    async fn exec(
+        &self,
+        src:     IpAddr,
+        dst:     SocketAddr,
+        waits:   &[Duration],
+        reset:   bool,
+        limiter: Option<&Limiter>,
+    ) -> Result<Response> {
+        let lease = self.state.reserve(src, dst).await?;
+        self.probe(dst, lease, waits, reset, limiter).await
     }
 
-    async fn probe(&self, probe: &Probe, mut lease: Lease<'_>, expiry: Duration) -> Result<Option<Duration>> {
-        let mut retries = 1;
+    async fn probe(
+        &self,
+        dst:       SocketAddr,
+        mut lease: Lease<'_>,
+        waits:     &[Duration],
+        reset:     bool,
+        limiter:   Option<&Limiter>,
+    ) -> Result<Response> {
+        let mut attempts = Vec::with_capacity(waits.len());
+
+        for &wait in waits {
+            if let Some(limiter) = limiter {
+                limiter.wait().await;
+            }
+
+            let probe = Probe::new(lease.src(), dst, random())?;
+            let sent  = self.send(&probe).await?;
+            let until = sent + wait;
+
+            attempts.push((probe, sent));
 
-        while retries > 0 {
-            let sent  = self.send(probe).await?;
-            let reply = timeout(expiry, &mut lease);
+            while let Ok(reply) = timeout_at(until.into(), &mut lease).await {
+                if let Ok(Reply { kind, ttl, when, clock }) = reply {
+                    if let Some((index, outcome)) = classify(&kind, &attempts, when) {
+                        if reset && outcome.is_open() {
+                            self.send(&attempts[index].0.reset()).await?;
+                        }
 
-            if let Ok(Ok(Reply { head, when })) = reply.await {
-                if head.syn && head.ack {
-                    if head.acknowledgment_number == probe.seq() + 1 {
-                        return Ok(Some(when.saturating_duration_since(sent)))
+                        let handshake = match kind {
+                            Kind::TCP(head) if outcome.is_open() => Some(Handshake::new(&head, ttl)),
+                            _                                    => None,
+                        };
+
+                        return Ok(Response { outcome, attempt: index + 1, handshake, clock: Some(clock) });
                     }
                 }
+                lease.renew();
             }
-
-            retries -= 1;
         }
 
-        Ok(None)
+        Ok(Response { outcome: Outcome::Timeout, attempt: attempts.len(), handshake: None, clock: None })
     }
 
     async fn send(&self, probe: &Probe) -> Result<Instant> {
@@ -85,3 +186,74 @@ impl Knocker {
         }
     }
 }
+
+fn classify(kind: &Kind, attempts: &[(Probe, Instant)], when: Instant) -> Option<(usize, Outcome)> {
+    let seq = match kind {
+        Kind::TCP(head)          => head.acknowledgment_number.wrapping_sub(1),
+        Kind::ICMP(_, _, quoted) => *quoted,
+    };
+
+    let index = attempts.iter().position(|(probe, _)| probe.seq() == seq)?;
+    let rtt   = when.saturating_duration_since(attempts[index].1);
+
+    let outcome = match kind {
+        Kind::TCP(head) if head.syn && head.ack                 => Outcome::Open(rtt),
+        Kind::TCP(head) if head.rst                             => Outcome::Closed(rtt),
+        Kind::TCP(..)                                           => return None,
+        Kind::ICMP(router, code, _) if filtered(*router, *code) => Outcome::Filtered,
+        Kind::ICMP(router, code, _)                             => Outcome::Unreachable(*router, *code),
+    };
+
+    Some((index, outcome))
+}
+
+fn filtered(router: IpAddr, code: u8) -> bool {
+    match router {
+        IpAddr::V4(..) => matches!(code, 9 | 10 | 13),
+        IpAddr::V6(..) => matches!(code, 1 | 5 | 6),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use etherparse::TcpHeader;
+    use super::*;
+
+    #[test]
+    fn classify_attempt() {
+        let base     = Instant::now();
+        let attempts = [attempt(100, base), attempt(200, base + SECOND)];
+
+        let reply = Kind::TCP(tcp(101, true, false));
+        assert_eq!(Some((0, Outcome::Open(SECOND * 2))), classify(&reply, &attempts, base + SECOND * 2));
+
+        let reply = Kind::TCP(tcp(201, false, true));
+        assert_eq!(Some((1, Outcome::Closed(SECOND))), classify(&reply, &attempts, base + SECOND * 2));
+
+        let reply = Kind::ICMP(IpAddr::from([192, 0, 2, 1]), 13, 200);
+        assert_eq!(Some((1, Outcome::Filtered)), classify(&reply, &attempts, base));
+
+        let reply = Kind::TCP(tcp(301, true, false));
+        assert_eq!(None, classify(&reply, &attempts, base));
+
+        let reply = Kind::TCP(tcp(101, false, false));
+        assert_eq!(None, classify(&reply, &attempts, base));
+    }
+
+    fn attempt(seq: u32, sent: Instant) -> (Probe, Instant) {
+        let src = SocketAddr::from(([192, 0, 2, 2], 40000));
+        let dst = SocketAddr::from(([192, 0, 2, 1], 80));
+        (Probe::new(src, dst, seq).unwrap(), sent)
+    }
+
+    fn tcp(ack: u32, syn: bool, rst: bool) -> TcpHeader {
+        let mut head = TcpHeader::new(80, 40000, 0, 0);
+        head.acknowledgment_number = ack;
+        head.ack = true;
+        head.syn = syn;
+        head.rst = rst;
+        head
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+}
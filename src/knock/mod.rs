@@ -1,9 +1,17 @@
+pub use backoff::Backoff;
+pub use handshake::Handshake;
 pub use knock::Knock;
 pub use knock::Knocker;
+pub use knock::Scan;
+pub use knock::Target;
+pub use outcome::{Outcome, Response};
 pub use probe::Probe;
-pub use reply::Reply;
+pub use reply::{Kind, Reply};
 
+mod backoff;
+mod handshake;
 mod knock;
+mod outcome;
 mod probe;
 mod reply;
 mod sock4;
@@ -0,0 +1,34 @@
+use std::net::IpAddr;
+use std::time::Duration;
+use crate::Clock;
+use super::handshake::Handshake;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Response {
+    pub outcome:   Outcome,
+    pub attempt:   usize,
+    pub handshake: Option<Handshake>,
+    pub clock:     Option<Clock>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Open(Duration),
+    Closed(Duration),
+    Filtered,
+    Unreachable(IpAddr, u8),
+    Timeout,
+}
+
+impl Outcome {
+    pub fn rtt(&self) -> Option<Duration> {
+        match self {
+            Self::Open(rtt) | Self::Closed(rtt) => Some(*rtt),
+            _                                   => None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self, Self::Open(..))
+    }
+}
@@ -1,5 +1,6 @@
 use std::io::Cursor;
 use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{anyhow, Result};
 use etherparse::*;
 
@@ -14,6 +15,7 @@ pub struct ProbeV4 {
     pub src: SocketAddrV4,
     pub dst: SocketAddrV4,
     pub seq: u32,
+    pub rst: bool,
 }
 
 #[derive(Debug)]
@@ -21,12 +23,13 @@ pub struct ProbeV6 {
     pub src: SocketAddrV6,
     pub dst: SocketAddrV6,
     pub seq: u32,
+    pub rst: bool,
 }
 
 impl Probe {
     pub fn new(src: SocketAddr, dst: SocketAddr, seq: u32) -> Result<Self> {
-        let probe4  = |src, dst| Probe::V4(ProbeV4 { src, dst, seq });
-        let probe6  = |src, dst| Probe::V6(ProbeV6 { src, dst, seq });
+        let probe4  = |src, dst| Probe::V4(ProbeV4 { src, dst, seq, rst: false });
+        let probe6  = |src, dst| Probe::V6(ProbeV6 { src, dst, seq, rst: false });
         let invalid = || anyhow!("mixed IPv4 and IPv6 addresses");
 
         match (src, dst) {
@@ -42,6 +45,14 @@ impl Probe {
             Self::V6(v6) => v6.seq,
         }
     }
+
+    pub fn reset(&self) -> Self {
+        let seq = self.seq().wrapping_add(1);
+        match self {
+            Self::V4(v4) => Self::V4(ProbeV4 { seq, rst: true, ..*v4 }),
+            Self::V6(v6) => Self::V6(ProbeV6 { seq, rst: true, ..*v6 }),
+        }
+    }
 }
 
 impl ProbeV4 {
@@ -53,7 +64,11 @@ impl ProbeV4 {
         let win = 5840;
 
         let pkt = PacketBuilder::ipv4(src, dst, 64);
-        let pkt = pkt.tcp(self.src.port(), self.dst.port(), self.seq, win).syn();
+        let pkt = pkt.tcp(self.src.port(), self.dst.port(), self.seq, win);
+        let pkt = match self.rst {
+            true  => pkt.rst(),
+            false => pkt.syn().options(&options())?,
+        };
 
         let n = pkt.size(0);
         pkt.write(&mut buf, &[])?;
@@ -71,7 +86,12 @@ impl ProbeV6 {
         let win = 5840;
 
         let mut pkt = TcpHeader::new(src, dst, self.seq, win);
-        pkt.syn = true;
+        pkt.syn = !self.rst;
+        pkt.rst = self.rst;
+
+        if pkt.syn {
+            pkt.set_options(&options())?;
+        }
 
         pkt.write(&mut buf)?;
         let n = buf.position() as usize;
@@ -79,3 +99,19 @@ impl ProbeV6 {
         Ok(&buf.into_inner()[..n])
     }
 }
+
+fn options() -> [TcpOptionElement; 5] {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let val = now.as_millis() as u32;
+
+    [
+        TcpOptionElement::MaximumSegmentSize(MSS),
+        TcpOptionElement::SelectiveAcknowledgementPermitted,
+        TcpOptionElement::Timestamp(val, 0),
+        TcpOptionElement::Noop,
+        TcpOptionElement::WindowScale(WSCALE),
+    ]
+}
+
+const MSS:    u16 = 1460;
+const WSCALE: u8  = 7;
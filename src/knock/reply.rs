@@ -1,14 +1,24 @@
+use std::net::IpAddr;
 use std::time::Instant;
 use etherparse::TcpHeader;
+use crate::Clock;
 
 #[derive(Debug)]
 pub struct Reply {
-    pub head: TcpHeader,
-    pub when: Instant,
+    pub kind:  Kind,
+    pub ttl:   Option<u8>,
+    pub when:  Instant,
+    pub clock: Clock,
+}
+
+#[derive(Debug)]
+pub enum Kind {
+    TCP(TcpHeader),
+    ICMP(IpAddr, u8, u32),
 }
 
 impl Reply {
-    pub fn new(head: TcpHeader, when: Instant) -> Self {
-        Self { head, when }
+    pub fn new(kind: Kind, ttl: Option<u8>, when: Instant, clock: Clock) -> Self {
+        Self { kind, ttl, when, clock }
     }
 }
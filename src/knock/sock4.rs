@@ -1,20 +1,26 @@
+use std::convert::{TryFrom, TryInto};
+use std::io::IoSliceMut;
 use std::net::{IpAddr, SocketAddr};
 use std::time::Instant;
 use std::sync::Arc;
 use anyhow::Result;
 use etherparse::{IpNumber, Ipv4Header, TcpHeader};
 use libc::{IPPROTO_TCP, c_int};
-use log::{debug, error};
 use raw_socket::tokio::prelude::*;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use crate::{Bind, RouteSocket};
-use super::{probe::ProbeV4, reply::Reply};
+use crate::clock::{self, stamp};
+use crate::icmp::{icmp4, IcmpV4Packet};
+use crate::recv::spawn;
+use super::probe::ProbeV4;
+use super::reply::{Kind, Reply};
 use super::state::State;
 
 pub struct Sock4 {
     sock:  Mutex<Arc<RawSocket>>,
     recv:  JoinHandle<()>,
+    icmp:  JoinHandle<()>,
     route: Mutex<RouteSocket>,
 }
 
@@ -22,26 +28,28 @@ impl Sock4 {
     pub async fn new(bind: &Bind, state: Arc<State>) -> Result<Self> {
         let ipv4 = Domain::ipv4();
         let tcp  = Protocol::from(IPPROTO_TCP);
+        let icmp = Protocol::icmpv4();
 
         let sock  = Arc::new(RawSocket::new(ipv4, Type::raw(), Some(tcp))?);
+        let icmp  = Arc::new(RawSocket::new(ipv4, Type::raw(), Some(icmp))?);
         let route = RouteSocket::new(bind.sa4()).await?;
 
         sock.bind(bind.sa4()).await?;
+        icmp.bind(bind.sa4()).await?;
+        clock::enable(&sock)?;
+        clock::enable(&icmp)?;
 
         let enable: c_int = 6;
         sock.set_sockopt(Level::IPV4, Name::IPV4_HDRINCL, &enable)?;
         let rx = sock.clone();
 
-        let recv = tokio::spawn(async move {
-            match recv(rx, state).await {
-                Ok(()) => debug!("recv finished"),
-                Err(e) => error!("recv failed: {}", e),
-            }
-        });
+        let recv = spawn("recv", recv(rx, state.clone()));
+        let icmp = spawn("icmp", unreachable(icmp, state));
 
         Ok(Self {
             sock:  Mutex::new(sock),
             recv:  recv,
+            icmp:  icmp,
             route: Mutex::new(route),
         })
     }
@@ -53,9 +61,10 @@ impl Sock4 {
         let dst = SocketAddr::V4(probe.dst);
 
         let sock = self.sock.lock().await;
+        let sent = Instant::now();
         sock.send_to(pkt, &dst).await?;
 
-        Ok(Instant::now())
+        Ok(sent)
     }
 
     pub async fn source(&self, dst: IpAddr, port: u16) -> Result<IpAddr> {
@@ -66,29 +75,84 @@ impl Sock4 {
 
 async fn recv(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
     let mut pkt = [0u8; 128];
+    let mut ctl = [0u8; 128];
+
     loop {
-        let (n, _from) = sock.recv_from(&mut pkt).await?;
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, _from) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
 
-        let now = Instant::now();
+        let (now, clock) = stamp(&ctl);
         let pkt = Ipv4Header::from_slice(&pkt[..n])?;
 
-        if let (Ipv4Header { protocol: TCP, source: src, destination: dst, .. }, tail) = pkt {
+        if let (Ipv4Header { protocol: TCP, source: src, destination: dst, time_to_live: ttl, .. }, tail) = pkt {
             let (head, _tail) = TcpHeader::from_slice(tail)?;
 
             let src = SocketAddr::new(IpAddr::from(src), head.source_port);
             let dst = SocketAddr::new(IpAddr::from(dst), head.destination_port);
 
             if let Some(tx) = state.remove(dst, src) {
-                let _ = tx.send(Reply::new(head, now));
+                let _ = tx.send(Reply::new(Kind::TCP(head), Some(ttl), now, clock));
             }
         }
     }
 }
 
+async fn unreachable(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
+    let mut pkt = [0u8; 1500];
+    let mut ctl = [0u8; 128];
+
+    loop {
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, from) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
+
+        let (now, clock) = stamp(&ctl);
+        let pkt = Ipv4Header::from_slice(&pkt[..n])?;
+
+        if let (Ipv4Header { protocol: ICMP, time_to_live: ttl, .. }, tail) = pkt {
+            let (code, quote) = match IcmpV4Packet::try_from(tail) {
+                Ok(IcmpV4Packet::Unreachable(what)) => match what {
+                    icmp4::Unreachable::Net(pkt)              => (0, pkt),
+                    icmp4::Unreachable::Host(pkt)             => (1, pkt),
+                    icmp4::Unreachable::Protocol(pkt)         => (2, pkt),
+                    icmp4::Unreachable::Port(pkt)             => (3, pkt),
+                    icmp4::Unreachable::Fragmentation(_, pkt) => (4, pkt),
+                    icmp4::Unreachable::Other(code, pkt)      => (code, pkt),
+                },
+                _                                   => continue,
+            };
+
+            if let Some((src, dst, seq)) = quoted(quote) {
+                if let Some(tx) = state.remove(src, dst) {
+                    let _ = tx.send(Reply::new(Kind::ICMP(from.ip(), code, seq), Some(ttl), now, clock));
+                }
+            }
+        }
+    }
+}
+
+fn quoted(pkt: &[u8]) -> Option<(SocketAddr, SocketAddr, u32)> {
+    let (ip, tail) = Ipv4Header::from_slice(pkt).ok()?;
+
+    if ip.protocol != TCP || tail.len() < 8 {
+        return None;
+    }
+
+    let sport = u16::from_be_bytes([tail[0], tail[1]]);
+    let dport = u16::from_be_bytes([tail[2], tail[3]]);
+    let seq   = u32::from_be_bytes(tail[4..8].try_into().ok()?);
+
+    let src = SocketAddr::new(IpAddr::from(ip.source), sport);
+    let dst = SocketAddr::new(IpAddr::from(ip.destination), dport);
+
+    Some((src, dst, seq))
+}
+
 impl Drop for Sock4 {
     fn drop(&mut self) {
         self.recv.abort();
+        self.icmp.abort();
     }
 }
 
-const TCP: u8 = IpNumber::Tcp as u8;
+const ICMP: u8 = IpNumber::Icmp as u8;
+const TCP:  u8 = IpNumber::Tcp as u8;
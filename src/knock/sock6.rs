@@ -1,21 +1,26 @@
+use std::convert::{TryFrom, TryInto};
 use std::io::IoSliceMut;
 use std::net::{IpAddr, SocketAddr};
 use std::time::Instant;
 use std::sync::Arc;
 use anyhow::Result;
-use etherparse::TcpHeader;
+use etherparse::{IpNumber, Ipv6Header, TcpHeader};
 use libc::{IPPROTO_TCP, c_int};
-use log::{debug, error};
 use raw_socket::tokio::prelude::*;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use crate::{Bind, RouteSocket};
-use super::{probe::ProbeV6, reply::Reply};
+use crate::clock::{self, stamp};
+use crate::icmp::{icmp6, IcmpV6Packet};
+use crate::recv::{pktinfo, spawn};
+use super::probe::ProbeV6;
+use super::reply::{Kind, Reply};
 use super::state::State;
 
 pub struct Sock6 {
     sock:  Mutex<Arc<RawSocket>>,
     recv:  JoinHandle<()>,
+    icmp:  JoinHandle<()>,
     route: Mutex<RouteSocket>,
 }
 
@@ -23,28 +28,32 @@ impl Sock6 {
     pub async fn new(bind: &Bind, state: Arc<State>) -> Result<Self> {
         let ipv6 = Domain::ipv6();
         let tcp  = Protocol::from(IPPROTO_TCP);
+        let icmp = Protocol::icmpv6();
 
         let sock  = Arc::new(RawSocket::new(ipv6, Type::raw(), Some(tcp))?);
+        let icmp  = Arc::new(RawSocket::new(ipv6, Type::raw(), Some(icmp))?);
         let route = RouteSocket::new(bind.sa6()).await?;
 
         sock.bind(bind.sa6()).await?;
+        icmp.bind(bind.sa6()).await?;
+        clock::enable(&sock)?;
+        clock::enable(&icmp)?;
 
         let offset: c_int = 16;
         let enable: c_int = 1;
-        sock.set_sockopt(Level::IPV6, Name::IPV6_CHECKSUM, &offset)?;
-        sock.set_sockopt(Level::IPV6, Name::IPV6_RECVPKTINFO, &enable)?;
+        sock.set_sockopt(Level::IPV6, Name::IPV6_CHECKSUM,      &offset)?;
+        sock.set_sockopt(Level::IPV6, Name::IPV6_RECVPKTINFO,  &enable)?;
+        sock.set_sockopt(Level::IPV6, Name::IPV6_RECVHOPLIMIT, &enable)?;
+        icmp.set_sockopt(Level::IPV6, Name::IPV6_RECVHOPLIMIT, &enable)?;
         let rx = sock.clone();
 
-        let recv = tokio::spawn(async move {
-            match recv(rx, state).await {
-                Ok(()) => debug!("recv finished"),
-                Err(e) => error!("recv failed: {}", e),
-            }
-        });
+        let recv = spawn("recv", recv(rx, state.clone()));
+        let icmp = spawn("icmp", unreachable(icmp, state));
 
         Ok(Self {
             sock:  Mutex::new(sock),
             recv:  recv,
+            icmp:  icmp,
             route: Mutex::new(route),
         })
     }
@@ -58,9 +67,10 @@ impl Sock6 {
         let dst = SocketAddr::V6(dst);
 
         let sock = self.sock.lock().await;
+        let sent = Instant::now();
         sock.send_to(pkt, &dst).await?;
 
-        Ok(Instant::now())
+        Ok(sent)
     }
 
     pub async fn source(&self, dst: IpAddr, port: u16) -> Result<IpAddr> {
@@ -71,34 +81,77 @@ impl Sock6 {
 
 async fn recv(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
     let mut pkt = [0u8; 64];
-    let mut ctl = [0u8; 64];
+    let mut ctl = [0u8; 128];
 
     loop {
         let iovec = &[IoSliceMut::new(&mut pkt)];
         let (n, src) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
 
-        let now = Instant::now();
+        let (now, clock) = stamp(&ctl);
         let pkt = TcpHeader::from_slice(&pkt[..n]);
-        let dst = CMsg::decode(&ctl).find_map(|msg| {
-            match msg {
-                CMsg::Ipv6PktInfo(info) => Some(info.addr().into()),
-                _                       => None,
-            }
-        });
+        let (dst, hops) = pktinfo(&ctl);
 
         if let (Ok((head, _tail)), Some(dst)) = (pkt, dst) {
             let src = SocketAddr::new(src.ip(), head.source_port);
             let dst = SocketAddr::new(dst, head.destination_port);
 
             if let Some(tx) = state.remove(dst, src) {
-                let _ = tx.send(Reply::new(head, now));
+                let _ = tx.send(Reply::new(Kind::TCP(head), hops, now, clock));
+            }
+        }
+    }
+}
+
+async fn unreachable(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
+    let mut pkt = [0u8; 1500];
+    let mut ctl = [0u8; 128];
+
+    loop {
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, from) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
+
+        let (now, clock) = stamp(&ctl);
+        let (_, hops)    = pktinfo(&ctl);
+
+        let (code, quote) = match IcmpV6Packet::try_from(&pkt[..n]) {
+            Ok(IcmpV6Packet::Unreachable(what)) => match what {
+                icmp6::Unreachable::Address(pkt)     => (3, pkt),
+                icmp6::Unreachable::Port(pkt)        => (4, pkt),
+                icmp6::Unreachable::Other(code, pkt) => (code, pkt),
+            },
+            _                                   => continue,
+        };
+
+        if let Some((src, dst, seq)) = quoted(quote) {
+            if let Some(tx) = state.remove(src, dst) {
+                let _ = tx.send(Reply::new(Kind::ICMP(from.ip(), code, seq), hops, now, clock));
             }
         }
     }
 }
 
+fn quoted(pkt: &[u8]) -> Option<(SocketAddr, SocketAddr, u32)> {
+    let (ip, tail) = Ipv6Header::from_slice(pkt).ok()?;
+
+    if ip.next_header != TCP || tail.len() < 8 {
+        return None;
+    }
+
+    let sport = u16::from_be_bytes([tail[0], tail[1]]);
+    let dport = u16::from_be_bytes([tail[2], tail[3]]);
+    let seq   = u32::from_be_bytes(tail[4..8].try_into().ok()?);
+
+    let src = SocketAddr::new(IpAddr::from(ip.source), sport);
+    let dst = SocketAddr::new(IpAddr::from(ip.destination), dport);
+
+    Some((src, dst, seq))
+}
+
 impl Drop for Sock6 {
     fn drop(&mut self) {
         self.recv.abort();
+        self.icmp.abort();
     }
 }
+
+const TCP: u8 = IpNumber::Tcp as u8;
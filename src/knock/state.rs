@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::future::Future;
+use std::io::{self, ErrorKind};
 use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use anyhow::Result;
 use futures::ready;
 use rand::prelude::*;
 use rand::distributions::Uniform;
 use parking_lot::Mutex;
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::sync::oneshot::{Sender, Receiver, channel, error::RecvError};
 use tokio::task;
 use super::reply::Reply;
@@ -28,6 +31,7 @@ struct Key(SocketAddr, SocketAddr);
 pub struct Lease<'s> {
     state: &'s State,
     rx:    Receiver<Reply>,
+    _sock: Socket,
     src:   SocketAddr,
     dst:   SocketAddr,
 }
@@ -40,18 +44,29 @@ impl State {
         }
     }
 
-    pub async fn reserve(&self, src: IpAddr, dst: SocketAddr) -> Lease<'_> {
+    pub async fn reserve(&self, src: IpAddr, dst: SocketAddr) -> Result<Lease<'_>> {
         let (tx, rx) = channel();
 
+        let domain = Domain::for_address(dst);
+        let stream = Type::STREAM;
+        let tcp    = Protocol::TCP;
+
         loop {
             let port = thread_rng().sample(self.range);
             let src  = SocketAddr::new(src, port);
             let key  = Key(src, dst);
+            let sock = Socket::new(domain, stream, Some(tcp))?;
+
+            let bound = match sock.bind(&src.into()).and_then(|_| quiet(&sock)) {
+                Ok(())                                     => true,
+                Err(e) if e.kind() == ErrorKind::AddrInUse => false,
+                Err(e)                                     => return Err(e.into()),
+            };
 
-            if let Entry::Vacant(e) = self.state.lock().entry(key) {
-                let lease = Lease { state: self, rx, src, dst };
+            if let (true, Entry::Vacant(e)) = (bound, self.state.lock().entry(key)) {
+                let lease = Lease { state: self, rx, _sock: sock, src, dst };
                 e.insert(tx);
-                return lease;
+                return Ok(lease);
             }
 
             task::yield_now().await;
@@ -67,6 +82,12 @@ impl Lease<'_> {
     pub fn src(&self) -> SocketAddr {
         self.src
     }
+
+    pub fn renew(&mut self) {
+        let (tx, rx) = channel();
+        self.rx = rx;
+        self.state.state.lock().insert(Key(self.src, self.dst), tx);
+    }
 }
 
 impl Drop for Lease<'_> {
@@ -85,3 +106,18 @@ impl Future for Lease<'_> {
         }
     }
 }
+
+#[cfg(target_os = "linux")]
+fn quiet(sock: &Socket) -> io::Result<()> {
+    let drop = libc::sock_filter { code: BPF_RET_K, jt: 0, jf: 0, k: 0 };
+    sock.attach_filter(&[drop])?;
+    sock.listen(1)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn quiet(_sock: &Socket) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+const BPF_RET_K: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
@@ -1,6 +1,10 @@
-#![allow(clippy::module_inception, clippy::redundant_field_names)]
+#![allow(clippy::module_inception, clippy::redundant_field_names, clippy::upper_case_acronyms)]
 
+pub use asn::AsTable;
 pub use bind::Bind;
+pub use clock::Clock;
+pub use clock::Timestamp;
+pub use limit::Limiter;
 pub use route::RouteSocket;
 
 pub use knock::Knock;
@@ -8,16 +12,30 @@ pub use knock::Knocker;
 
 pub use ping::Ping;
 pub use ping::Pinger;
+pub use ping::PingStats;
+pub use ping::PingSummary;
 
+pub use resolve::Dns;
+pub use resolve::Names;
+pub use resolve::Resolver;
+
+pub use trace::Mda;
+pub use trace::Mtr;
 pub use trace::Node;
+pub use trace::Pmtu;
 pub use trace::Protocol;
 pub use trace::Trace;
 pub use trace::Tracer;
 
+pub mod asn;
 pub mod icmp;
 pub mod knock;
 pub mod ping;
+pub mod resolve;
 pub mod trace;
 
 mod bind;
+mod clock;
+mod limit;
+mod recv;
 mod route;
@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+use futures::Stream;
+use futures::stream::unfold;
+use parking_lot::Mutex;
+use tokio::time::{interval_at, sleep_until, timeout_at, MissedTickBehavior};
+
+#[derive(Debug)]
+pub struct Limiter {
+    gap:  Duration,
+    next: Mutex<Instant>,
+}
+
+impl Limiter {
+    pub fn new(rate: u32) -> Self {
+        let gap = match rate {
+            0    => Duration::ZERO,
+            rate => Duration::from_secs(1) / rate,
+        };
+        Self { gap, next: Mutex::new(Instant::now()) }
+    }
+
+    pub async fn wait(&self) {
+        let now  = Instant::now();
+        let slot = {
+            let mut next = self.next.lock();
+            let slot = now.max(*next);
+            *next = slot + self.gap;
+            slot
+        };
+
+        if slot > now {
+            sleep_until(slot.into()).await;
+        }
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+pub fn ticks(interval: Duration, deadline: Option<Duration>) -> impl Stream<Item = Instant> {
+    let start = Instant::now();
+    let until = deadline.map(|deadline| start + deadline);
+
+    let mut ticker = interval_at(start.into(), interval.max(INTERVAL_MIN));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    unfold(ticker, move |mut ticker| async move {
+        let next = match until {
+            Some(until) => timeout_at(until.into(), ticker.tick()).await.ok()?,
+            None        => ticker.tick().await,
+        }.into_std();
+
+        if matches!(until, Some(until) if next >= until) {
+            return None;
+        }
+
+        Some((next, ticker))
+    })
+}
+
+const INTERVAL_MIN: Duration = Duration::from_nanos(1);
@@ -1,9 +1,19 @@
+pub use mode::Mode;
 pub use ping::Ping;
 pub use ping::Pinger;
 pub use probe::Probe;
+pub use reply::Echo;
+pub use reply::IcmpError;
+pub use reply::Reply;
+pub use stats::PingStats;
+pub use stats::PingSummary;
 
+mod mode;
 mod ping;
 mod probe;
+mod reply;
 mod sock4;
 mod sock6;
+mod socket;
 mod state;
+mod stats;
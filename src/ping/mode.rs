@@ -0,0 +1,36 @@
+use std::io::{Error, ErrorKind};
+use anyhow::Result;
+use log::debug;
+use raw_socket::prelude::{Domain, Protocol, Type};
+use super::socket::Socket;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    Auto,
+    Raw,
+    Dgram,
+}
+
+impl Mode {
+    pub fn socket(self, domain: Domain, proto: Protocol) -> Result<(Socket, Mode)> {
+        let raw   = || Socket::new(domain, Type::raw(),   Some(proto));
+        let dgram = || Socket::new(domain, Type::dgram(), Some(proto));
+
+        match self {
+            Mode::Raw   => Ok((raw()?,   Mode::Raw)),
+            Mode::Dgram => Ok((dgram()?, Mode::Dgram)),
+            Mode::Auto  => match raw() {
+                Ok(sock)             => Ok((sock, Mode::Raw)),
+                Err(e) if denied(&e) => {
+                    debug!("raw socket unavailable, using dgram: {}", e);
+                    Mode::Dgram.socket(domain, proto)
+                },
+                Err(e)               => Err(e.into()),
+            },
+        }
+    }
+}
+
+fn denied(e: &Error) -> bool {
+    e.kind() == ErrorKind::PermissionDenied
+}
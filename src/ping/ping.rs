@@ -1,21 +1,35 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use anyhow::Result;
-use futures::{Stream, StreamExt};
-use futures::stream::try_unfold;
+use futures::{Stream, StreamExt, TryStreamExt};
+use futures::stream::{select_all, try_unfold};
 use rand::random;
-use tokio::time::timeout;
-use crate::Bind;
+use tokio::time::{timeout, timeout_at};
+use crate::{Bind, Limiter};
+use crate::clock::Sent;
+use crate::limit::ticks;
 use super::{sock4::Sock4, sock6::Sock6};
+use super::mode::Mode;
 use super::probe::Probe;
-use super::state::State;
+use super::reply::{Echo, IcmpError, Packet, Reply};
+use super::state::{Lease, State};
+use super::stats::PingStats;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Ping {
-    pub addr:   IpAddr,
-    pub count:  usize,
-    pub expiry: Duration,
+    pub addr:     IpAddr,
+    pub count:    usize,
+    pub expiry:   Duration,
+    pub interval: Duration,
+    pub deadline: Option<Duration>,
+    pub grace:    Duration,
+    pub size:     usize,
+    pub pattern:  Vec<u8>,
+    pub ttl:      u8,
+    pub dscp:     u8,
+    pub ecn:      u8,
+    pub df:       bool,
 }
 
 pub struct Pinger {
@@ -24,41 +38,145 @@ pub struct Pinger {
     state: Arc<State>,
 }
 
+impl Default for Ping {
+    fn default() -> Self {
+        Self {
+            addr:     IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            count:    4,
+            expiry:   Duration::from_secs(1),
+            interval: Duration::from_secs(1),
+            deadline: None,
+            grace:    Duration::ZERO,
+            size:     56,
+            pattern:  Vec::new(),
+            ttl:      64,
+            dscp:     0,
+            ecn:      0,
+            df:       false,
+        }
+    }
+}
+
 impl Pinger {
     pub async fn new(bind: &Bind) -> Result<Self> {
+        Self::with_mode(bind, Mode::Auto).await
+    }
+
+    pub async fn with_mode(bind: &Bind, mode: Mode) -> Result<Self> {
         let state = Arc::new(State::default());
 
-        let sock4 = Sock4::new(bind, state.clone()).await?;
-        let sock6 = Sock6::new(bind, state.clone()).await?;
+        let sock4 = Sock4::new(bind, mode, state.clone()).await?;
+        let sock6 = Sock6::new(bind, mode, state.clone()).await?;
 
         Ok(Self { sock4, sock6, state })
     }
 
-    pub fn ping(&self, ping: &Ping) -> impl Stream<Item = Result<Option<Duration>>> + '_ {
-        let Ping { addr, count, expiry } = *ping;
+    pub fn ping(&self, ping: &Ping) -> impl Stream<Item = Result<Reply>> + '_ {
+        self.stream(ping, None)
+    }
+
+    pub fn ping_all<'a>(&'a self, pings: &[Ping], limiter: &'a Limiter) -> impl Stream<Item = (usize, Result<Reply>)> + 'a {
+        select_all(pings.iter().enumerate().map(|(index, ping)| {
+            self.stream(ping, Some(limiter)).map(move |r| (index, r)).boxed()
+        }))
+    }
 
-        try_unfold(0, move |seq| async move {
-            let ident = random();
-            let probe = Probe::new(addr, ident, seq);
-            let rtt   = self.probe(&probe, expiry).await?;
-            Ok(Some((rtt, (seq.wrapping_add(1)))))
-        }).take(count)
+    pub async fn ping_summary(&self, ping: &Ping) -> Result<PingStats> {
+        self.ping(ping).try_fold(PingStats::new(), |mut stats, reply| async move {
+            stats.record(&reply);
+            Ok(stats)
+        }).await
     }
 
-    async fn probe(&self, probe: &Probe, expiry: Duration) -> Result<Option<Duration>> {
-        let rx   = self.state.insert(probe.token);
-        let sent = self.send(probe).await?;
+    fn stream<'a>(&'a self, ping: &Ping, limiter: Option<&'a Limiter>) -> impl Stream<Item = Result<Reply>> + 'a {
+        let Ping { addr, count, expiry, interval, deadline, grace, size, ttl, dscp, ecn, df, .. } = *ping;
+        let pattern = ping.pattern.clone();
+
+        ticks(interval, deadline).take(count).enumerate().map(move |(seq, _)| {
+            let ident = self.ident(addr).unwrap_or_else(random);
+            let probe = Probe::new(addr, ident, seq as u16);
+            let probe = probe.payload(size, &pattern).and_then(|probe| {
+                probe.header(ttl, dscp, ecn, df)
+            });
+            self.probe(probe, limiter, expiry, grace).boxed()
+        }).flatten_unordered(None)
+    }
 
-        Ok(match timeout(expiry, rx).await {
-            Ok(r)  => Some(r?.saturating_duration_since(sent)),
-            Err(_) => None,
+    fn probe<'a>(
+        &'a self,
+        probe:   Result<Probe>,
+        limiter: Option<&'a Limiter>,
+        expiry:  Duration,
+        grace:   Duration,
+    ) -> impl Stream<Item = Result<Reply>> + 'a {
+        try_unfold(Phase::Send(probe), move |phase| async move {
+            match phase {
+                Phase::Send(probe)                    => self.first(probe?, limiter, expiry, grace).await,
+                Phase::Wait(probe, lease, sent, late) => Ok(extra(probe, lease, sent, late, expiry + grace).await),
+                Phase::Done                           => Ok(None),
+            }
         })
     }
 
-    async fn send(&self, probe: &Probe) -> Result<Instant> {
+    async fn first<'a>(
+        &'a self,
+        probe:   Probe,
+        limiter: Option<&Limiter>,
+        expiry:  Duration,
+        grace:   Duration,
+    ) -> Result<Option<(Reply, Phase<'a>)>> {
+        if let Some(limiter) = limiter {
+            limiter.wait().await;
+        }
+
+        let mut lease = self.state.insert(probe.key(), probe.token);
+        let sent      = self.send(&probe).await?;
+
+        let (reply, late) = match timeout(expiry, lease.recv()).await {
+            Ok(Some(pkt)) => (reply(&probe, pkt, &sent, false, false), false),
+            _             => (Reply::Timeout(probe.seq), true),
+        };
+
+        let next = match grace.is_zero() {
+            true  => Phase::Done,
+            false => Phase::Wait(probe, lease, sent, late),
+        };
+
+        Ok(Some((reply, next)))
+    }
+
+    fn ident(&self, addr: IpAddr) -> Option<u16> {
+        match addr {
+            IpAddr::V4(..) => self.sock4.ident(),
+            IpAddr::V6(..) => self.sock6.ident(),
+        }
+    }
+
+    async fn send(&self, probe: &Probe) -> Result<Sent> {
         match probe.addr {
             IpAddr::V4(_) => self.sock4.send(probe).await,
             IpAddr::V6(_) => self.sock6.send(probe).await,
         }
     }
 }
+
+enum Phase<'a> {
+    Send(Result<Probe>),
+    Wait(Probe, Lease<'a>, Sent, bool),
+    Done,
+}
+
+async fn extra(probe: Probe, mut lease: Lease<'_>, sent: Sent, late: bool, window: Duration) -> Option<(Reply, Phase<'_>)> {
+    let dup   = lease.seen();
+    let until = sent.when().0 + window;
+    let pkt   = timeout_at(until.into(), lease.recv()).await.ok()??;
+    let reply = reply(&probe, pkt, &sent, dup, late);
+    Some((reply, Phase::Wait(probe, lease, sent, late)))
+}
+
+fn reply(probe: &Probe, pkt: Packet, sent: &Sent, dup: bool, late: bool) -> Reply {
+    match pkt.error {
+        Some((kind, code)) => Reply::Error(IcmpError::new(probe, pkt, sent, kind, code)),
+        None               => Reply::Echo(Echo::new(probe, pkt, sent, dup, late)),
+    }
+}
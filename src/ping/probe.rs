@@ -2,24 +2,78 @@ use std::array::TryFromSliceError;
 use std::convert::{TryFrom, TryInto};
 use std::net::IpAddr;
 use anyhow::{anyhow, Result};
+use etherparse::{IpNumber, Ipv4Header, Ipv6Header};
 use rand::random;
 use crate::icmp::{icmp4, icmp6};
 
 #[derive(Debug)]
 pub struct Probe {
-    pub addr:  IpAddr,
-    pub id:    u16,
-    pub seq:   u16,
-    pub token: Token,
+    pub addr:    IpAddr,
+    pub id:      u16,
+    pub seq:     u16,
+    pub token:   Token,
+    pub size:    usize,
+    pub pattern: Vec<u8>,
+    pub ttl:     u8,
+    pub tos:     u8,
+    pub df:      bool,
 }
 
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
-pub struct Token([u8; 16]);
+pub struct Token([u8; TOKEN_SIZE]);
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Key(IpAddr, u16, u16);
+
+pub const TOKEN_SIZE:  usize = 16;
+pub const PAYLOAD_MIN: usize = TOKEN_SIZE;
+pub const PAYLOAD_MAX: usize = 65507;
+
+const HEADER_SIZE: usize = icmp4::HEADER_SIZE;
+
+const DSCP_MAX: u8 = 0x3f;
+const ECN_MAX:  u8 = 0x03;
+
+const ICMP4: u8 = IpNumber::Icmp     as u8;
+const ICMP6: u8 = IpNumber::IPv6Icmp as u8;
 
 impl Probe {
     pub fn new(addr: IpAddr, id: u16, seq: u16) -> Self {
         let token = Token(random());
-        Self { addr, id, seq, token }
+        Self {
+            addr:    addr,
+            id:      id,
+            seq:     seq,
+            token:   token,
+            size:    TOKEN_SIZE,
+            pattern: Vec::new(),
+            ttl:     64,
+            tos:     0,
+            df:      false,
+        }
+    }
+
+    pub fn payload(mut self, size: usize, pattern: &[u8]) -> Result<Self> {
+        if !(PAYLOAD_MIN..=PAYLOAD_MAX).contains(&size) {
+            return Err(anyhow!("payload size must be {}-{}", PAYLOAD_MIN, PAYLOAD_MAX));
+        }
+
+        self.size    = size;
+        self.pattern = pattern.to_vec();
+
+        Ok(self)
+    }
+
+    pub fn header(mut self, ttl: u8, dscp: u8, ecn: u8, df: bool) -> Result<Self> {
+        if dscp > DSCP_MAX || ecn > ECN_MAX {
+            return Err(anyhow!("invalid DSCP or ECN value"));
+        }
+
+        self.ttl = ttl;
+        self.tos = dscp << 2 | ecn;
+        self.df  = df;
+
+        Ok(self)
     }
 
     pub fn encode<'a>(&self, buf: &'a mut [u8]) -> Result<&'a mut [u8]> {
@@ -28,7 +82,7 @@ impl Probe {
             IpAddr::V6(_) => (icmp6::ECHO_REQUEST, icmp6::HEADER_SIZE),
         };
 
-        n += self.token.0.len();
+        n += self.size;
 
         if buf.len() < n {
             return Err(anyhow!("short buffer"));
@@ -38,16 +92,76 @@ impl Probe {
         buf[2..4].copy_from_slice(&0u16.to_be_bytes());
         buf[4..6].copy_from_slice(&self.id.to_be_bytes());
         buf[6..8].copy_from_slice(&self.seq.to_be_bytes());
-        buf[8..n].copy_from_slice(&self.token.0);
+
+        let (token, fill) = buf[8..n].split_at_mut(TOKEN_SIZE);
+        token.copy_from_slice(&self.token.0);
+
+        for (i, byte) in fill.iter_mut().enumerate() {
+            *byte = self.fill(i);
+        }
 
         Ok(&mut buf[0..n])
     }
+
+    pub fn decode4(pkt: &[u8]) -> Result<(Key, Option<Token>)> {
+        let (head, tail) = Ipv4Header::from_slice(pkt)?;
+        match head.protocol {
+            ICMP4 => decode(head.destination.into(), icmp4::ECHO_REQUEST, tail),
+            other => Err(anyhow!("unsupported protocol: {}", other)),
+        }
+    }
+
+    pub fn decode6(pkt: &[u8]) -> Result<(Key, Option<Token>)> {
+        let (head, tail) = Ipv6Header::from_slice(pkt)?;
+        match head.next_header {
+            ICMP6 => decode(head.destination.into(), icmp6::ECHO_REQUEST, tail),
+            other => Err(anyhow!("unsupported protocol: {}", other)),
+        }
+    }
+
+    pub fn echo4(addr: IpAddr, pkt: &[u8]) -> Result<(Key, Option<Token>)> {
+        decode(addr, icmp4::ECHO_REQUEST, pkt)
+    }
+
+    pub fn echo6(addr: IpAddr, pkt: &[u8]) -> Result<(Key, Option<Token>)> {
+        decode(addr, icmp6::ECHO_REQUEST, pkt)
+    }
+
+    pub fn key(&self) -> Key {
+        Key(self.addr, self.id, self.seq)
+    }
+
+    pub fn verify(&self, data: &[u8]) -> bool {
+        data.len() == self.size && data[TOKEN_SIZE..].iter().enumerate().all(|(i, byte)| {
+            *byte == self.fill(i)
+        })
+    }
+
+    fn fill(&self, index: usize) -> u8 {
+        match self.pattern.len() {
+            0 => index as u8,
+            n => self.pattern[index % n],
+        }
+    }
+}
+
+fn decode(addr: IpAddr, request: u8, tail: &[u8]) -> Result<(Key, Option<Token>)> {
+    if tail.len() < HEADER_SIZE || tail[0] != request {
+        return Err(anyhow!("invalid echo request"));
+    }
+
+    let id    = u16::from_be_bytes(tail[4..6].try_into()?);
+    let seq   = u16::from_be_bytes(tail[6..8].try_into()?);
+    let token = tail[HEADER_SIZE..].try_into().ok();
+
+    Ok((Key(addr, id, seq), token))
 }
 
 impl TryFrom<&[u8]> for Token {
     type Error = TryFromSliceError;
 
     fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
-        Ok(Self(buf.try_into()?))
+        let n = buf.len().min(TOKEN_SIZE);
+        Ok(Self(buf[..n].try_into()?))
     }
 }
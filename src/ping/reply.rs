@@ -0,0 +1,98 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use crate::Clock;
+use crate::clock::Sent;
+use super::probe::Probe;
+
+#[derive(Clone, Debug)]
+pub enum Reply {
+    Echo(Echo),
+    Error(IcmpError),
+    Timeout(u16),
+}
+
+#[derive(Clone, Debug)]
+pub struct Echo {
+    pub seq:     u16,
+    pub addr:    IpAddr,
+    pub ttl:     u8,
+    pub tos:     u8,
+    pub size:    usize,
+    pub rtt:     Duration,
+    pub dup:     bool,
+    pub late:    bool,
+    pub corrupt: bool,
+    pub clock:   Clock,
+}
+
+#[derive(Clone, Debug)]
+pub struct IcmpError {
+    pub seq:   u16,
+    pub addr:  IpAddr,
+    pub kind:  u8,
+    pub code:  u8,
+    pub rtt:   Duration,
+    pub clock: Clock,
+}
+
+#[derive(Debug)]
+pub struct Packet {
+    pub addr:  IpAddr,
+    pub ttl:   u8,
+    pub tos:   u8,
+    pub size:  usize,
+    pub data:  Vec<u8>,
+    pub when:  Instant,
+    pub clock: Clock,
+    pub error: Option<(u8, u8)>,
+}
+
+impl Reply {
+    pub fn seq(&self) -> u16 {
+        match self {
+            Self::Echo(echo)   => echo.seq,
+            Self::Error(error) => error.seq,
+            Self::Timeout(seq) => *seq,
+        }
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        match self {
+            Self::Echo(echo) => Some(echo.rtt),
+            Self::Error(_)   => None,
+            Self::Timeout(_) => None,
+        }
+    }
+}
+
+impl Echo {
+    pub fn new(probe: &Probe, pkt: Packet, sent: &Sent, dup: bool, late: bool) -> Self {
+        let (sent, send) = sent.when();
+        Self {
+            seq:     probe.seq,
+            addr:    pkt.addr,
+            ttl:     pkt.ttl,
+            tos:     pkt.tos,
+            size:    pkt.size,
+            rtt:     pkt.when.saturating_duration_since(sent),
+            dup:     dup,
+            late:    late,
+            corrupt: !probe.verify(&pkt.data),
+            clock:   Clock { send, ..pkt.clock },
+        }
+    }
+}
+
+impl IcmpError {
+    pub fn new(probe: &Probe, pkt: Packet, sent: &Sent, kind: u8, code: u8) -> Self {
+        let (sent, send) = sent.when();
+        Self {
+            seq:   probe.seq,
+            addr:  pkt.addr,
+            kind:  kind,
+            code:  code,
+            rtt:   pkt.when.saturating_duration_since(sent),
+            clock: Clock { send, ..pkt.clock },
+        }
+    }
+}
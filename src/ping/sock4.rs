@@ -1,46 +1,87 @@
 use std::convert::{TryFrom, TryInto};
-use std::net::SocketAddr;
+use std::io::IoSliceMut;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Instant;
 use anyhow::Result;
 use etherparse::{IpNumber, Ipv4Header};
+use libc::{IPPROTO_IP, IP_TOS, IP_TTL, c_int};
 use log::{debug, error};
-use raw_socket::{Domain, Type, Protocol};
-use raw_socket::tokio::RawSocket;
+use raw_socket::control::Raw;
+use raw_socket::prelude::*;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use crate::Bind;
+use crate::clock::{self, Sent, Transmit, stamp};
 use crate::icmp::IcmpV4Packet;
-use crate::icmp::icmp4::checksum;
+use crate::icmp::icmp4::{checksum, HEADER_SIZE};
+use super::mode::Mode;
 use super::probe::Probe;
+use super::reply::Packet;
+use super::socket::{IP_RECVERR, Socket, deferred, offender, stamped};
 use super::state::State;
 
 pub struct Sock4 {
-    recv: JoinHandle<()>,
-    sock: Mutex<Arc<RawSocket>>,
+    recv:  JoinHandle<()>,
+    errq:  JoinHandle<()>,
+    xmit:  Arc<Transmit>,
+    sock:  Mutex<Arc<Socket>>,
+    ident: Option<u16>,
 }
 
 impl Sock4 {
-    pub async fn new(bind: &Bind, state: Arc<State>) -> Result<Self> {
-        let raw   = Type::raw();
+    pub async fn new(bind: &Bind, mode: Mode, state: Arc<State>) -> Result<Self> {
         let icmp4 = Protocol::icmpv4();
 
-        let sock = Arc::new(RawSocket::new(Domain::ipv4(), raw, Some(icmp4))?);
-        sock.bind(bind.sa4()).await?;
-        let rx = sock.clone();
+        let (sock, mode) = mode.socket(Domain::ipv4(), icmp4)?;
+        let sock = Arc::new(sock);
+        sock.bind(bind.sa4())?;
+        clock::enable(&sock)?;
+
+        let ident = match mode {
+            Mode::Dgram => Some(sock.local_addr()?.port()),
+            _           => None,
+        };
+
+        let xmit = Arc::new(Transmit::default());
+        clock::transmit(&sock, &xmit)?;
+
+        if mode == Mode::Dgram {
+            let enable: c_int = 1;
+            sock.set_sockopt(Level::IPV4, Name::from(libc::IP_RECVTTL), &enable)?;
+            sock.set_sockopt(Level::IPV4, Name::from(libc::IP_RECVTOS), &enable)?;
+            sock.set_sockopt(Level::IPV4, Name::from(IP_RECVERR),       &enable)?;
+        }
+
+        let rx   = sock.clone();
+        let tx   = state.clone();
+        let sent = xmit.clone();
 
         let recv = tokio::spawn(async move {
-            match recv(rx, state).await {
+            match recv(rx, mode, state, sent).await {
                 Ok(()) => debug!("recv finished"),
                 Err(e) => error!("recv failed: {}", e),
             }
         });
 
-        Ok(Self { recv, sock: Mutex::new(sock) })
+        let rx   = sock.clone();
+        let sent = xmit.clone();
+
+        let errq = tokio::spawn(async move {
+            match errors(rx, tx, sent).await {
+                Ok(()) => debug!("error queue finished"),
+                Err(e) => error!("error queue failed: {}", e),
+            }
+        });
+
+        Ok(Self { recv, errq, xmit, sock: Mutex::new(sock), ident })
+    }
+
+    pub fn ident(&self) -> Option<u16> {
+        self.ident
     }
 
-    pub async fn send(&self, probe: &Probe) -> Result<Instant> {
-        let mut pkt = [0u8; 64];
+    pub async fn send(&self, probe: &Probe) -> Result<Sent> {
+        let mut pkt = vec![0u8; HEADER_SIZE + probe.size];
 
         let pkt = probe.encode(&mut pkt)?;
         let cksum = checksum(pkt).to_be_bytes();
@@ -48,36 +89,194 @@ impl Sock4 {
 
         let addr = SocketAddr::new(probe.addr, 0);
         let sock = self.sock.lock().await;
-        sock.send_to(pkt, &addr).await?;
 
-        Ok(Instant::now())
+        let ttl = c_int::from(probe.ttl);
+        let tos = c_int::from(probe.tos);
+        sock.set_sockopt(Level::IPV4, Name::from(IP_TTL), &ttl)?;
+        sock.set_sockopt(Level::IPV4, Name::from(IP_TOS), &tos)?;
+        dontfrag(&sock, probe.df)?;
+
+        let sent = self.xmit.sent();
+        if let Err(e) = sock.send_to(pkt, &addr).await {
+            clock::transmit(&*sock, &self.xmit)?;
+            return Err(e.into());
+        }
+
+        Ok(sent)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn dontfrag(sock: &Socket, df: bool) -> Result<()> {
+    let value = match df {
+        true  => libc::IP_PMTUDISC_DO,
+        false => libc::IP_PMTUDISC_DONT,
+    };
+    Ok(sock.set_sockopt(Level::IPV4, Name::from(libc::IP_MTU_DISCOVER), &value)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn dontfrag(_sock: &Socket, df: bool) -> Result<()> {
+    match df {
+        true  => Err(anyhow::anyhow!("DF not supported")),
+        false => Ok(()),
+    }
+}
+
+async fn recv(sock: Arc<Socket>, mode: Mode, state: Arc<State>, xmit: Arc<Transmit>) -> Result<()> {
+    let mut pkt  = vec![0u8; PACKET_SIZE];
+    let mut ctl  = [0u8; 256];
+    let mut err  = vec![0u8; PACKET_SIZE];
+    let mut ectl = [0u8; 256];
+
+    loop {
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, from) = match sock.recv_msg(iovec, &mut ctl).await {
+            Ok((n, from))          => (n, from),
+            Err(e) if deferred(&e) => continue,
+            Err(e)                 => return Err(e.into()),
+        };
+
+        flush(&sock, &state, &xmit, &mut err, &mut ectl);
+
+        let (now, clock) = stamp(&ctl);
+        let pkt = &pkt[..n];
+
+        let (ttl, tos, tail) = match mode {
+            Mode::Dgram => {
+                let (ttl, tos) = control(&ctl);
+                (ttl, tos, pkt)
+            },
+            _ => match Ipv4Header::from_slice(pkt) {
+                Ok((ip @ Ipv4Header { protocol: ICMP4, .. }, tail)) => (ip.time_to_live, tos(&ip), tail),
+                Ok(_)                                               => continue,
+                Err(e)                                              => {
+                    debug!("invalid packet from {}: {}", from, e);
+                    continue;
+                },
+            },
+        };
+
+        let pkt = match IcmpV4Packet::try_from(tail) {
+            Ok(pkt) => pkt,
+            Err(e)  => {
+                debug!("invalid packet from {}: {}", from, e);
+                continue;
+            },
+        };
+
+        let (error, data, token) = match pkt {
+            IcmpV4Packet::EchoReply(echo) => (None, echo.data, echo.data.try_into().ok()),
+            IcmpV4Packet::Unreachable(..) | IcmpV4Packet::TimeExceeded(..) => {
+                let quote = &tail[HEADER_SIZE..];
+                let token = match Probe::decode4(quote) {
+                    Ok((key, token)) => token.or_else(|| state.lookup(&key)),
+                    Err(_)           => None,
+                };
+                (Some((tail[0], tail[1])), quote, token)
+            },
+            _ => continue,
+        };
+
+        if let Some(tx) = token.and_then(|token| state.sender(&token)) {
+            let _ = tx.send(Packet {
+                addr:  from.ip(),
+                ttl:   ttl,
+                tos:   tos,
+                size:  tail.len(),
+                data:  data.to_vec(),
+                when:  now,
+                clock: clock,
+                error: error,
+            });
+        }
     }
 }
 
-async fn recv(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
-    let mut pkt = [0u8; 128];
+async fn errors(sock: Arc<Socket>, state: Arc<State>, xmit: Arc<Transmit>) -> Result<()> {
+    let mut pkt = vec![0u8; PACKET_SIZE];
+    let mut ctl = [0u8; 256];
+
     loop {
-        let (n, _) = sock.recv_from(&mut pkt).await?;
-
-        let now = Instant::now();
-        let pkt = Ipv4Header::from_slice(&pkt[..n])?;
-
-        if let (Ipv4Header { protocol: ICMP4, .. }, tail) = pkt {
-            if let IcmpV4Packet::EchoReply(echo) = IcmpV4Packet::try_from(tail)? {
-                if let Ok(token) = echo.data.try_into() {
-                    if let Some(tx) = state.remove(&token) {
-                        let _ = tx.send(now);
-                    }
-                }
+        let (n, len, dst) = sock.recv_err(&mut pkt, &mut ctl).await?;
+        error(&pkt[..n], &ctl[..len], dst, &state, &xmit);
+    }
+}
+
+fn flush(sock: &Socket, state: &State, xmit: &Transmit, pkt: &mut [u8], ctl: &mut [u8]) {
+    while let Ok((n, len, dst)) = sock.try_recv_err(pkt, ctl) {
+        error(&pkt[..n], &ctl[..len], dst, state, xmit);
+    }
+}
+
+fn error(pkt: &[u8], ctl: &[u8], dst: Option<IpAddr>, state: &State, xmit: &Transmit) {
+    if let (Some(id), Some(when)) = (stamped(ctl), clock::transmitted(ctl)) {
+        xmit.stamp(id, when);
+        return;
+    }
+
+    let (now, clock) = stamp(ctl);
+    let (ttl, tos)   = control(ctl);
+
+    let (addr, kind, code) = match offender(ctl) {
+        Some(offender) => offender,
+        None           => return,
+    };
+
+    let data  = pkt.get(HEADER_SIZE..).unwrap_or_default();
+    let token = data.try_into().ok().or_else(|| {
+        let (key, _) = Probe::echo4(dst?, pkt).ok()?;
+        state.lookup(&key)
+    });
+
+    if let Some(tx) = token.and_then(|token| state.sender(&token)) {
+        let _ = tx.send(Packet {
+            addr:  addr,
+            ttl:   ttl,
+            tos:   tos,
+            size:  pkt.len(),
+            data:  data.to_vec(),
+            when:  now,
+            clock: clock,
+            error: Some((kind, code)),
+        });
+    }
+}
+
+fn control(ctl: &[u8]) -> (u8, u8) {
+    let mut ttl = 0;
+    let mut tos = 0;
+
+    for msg in CMsg::decode(ctl) {
+        if let CMsg::Raw(Raw { level: IPPROTO_IP, kind, data }) = msg {
+            match (kind, data) {
+                (IP_TTL, _)       => ttl = int(data) as u8,
+                (IP_TOS, [b, ..]) => tos = *b,
+                _                 => (),
             }
         }
     }
+
+    (ttl, tos)
+}
+
+fn int(data: &[u8]) -> c_int {
+    match data.try_into() {
+        Ok(bytes) => c_int::from_ne_bytes(bytes),
+        Err(_)    => 0,
+    }
+}
+
+fn tos(ip: &Ipv4Header) -> u8 {
+    ip.differentiated_services_code_point << 2 | ip.explicit_congestion_notification
 }
 
 impl Drop for Sock4 {
     fn drop(&mut self) {
         self.recv.abort();
+        self.errq.abort();
     }
 }
 
-const ICMP4: u8 = IpNumber::Icmp as u8;
+const ICMP4:       u8    = IpNumber::Icmp as u8;
+const PACKET_SIZE: usize = 65535;
@@ -1,74 +1,249 @@
 use std::convert::{TryFrom, TryInto};
-use std::net::SocketAddr;
+use std::io::{IoSlice, IoSliceMut};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Instant;
 use anyhow::Result;
+use libc::{IPPROTO_IPV6, IPV6_DONTFRAG, IPV6_RECVTCLASS, IPV6_TCLASS, c_int};
 use log::{debug, error};
-use raw_socket::{Domain, Type, Protocol};
-use raw_socket::tokio::RawSocket;
+use raw_socket::control::Raw;
+use raw_socket::prelude::*;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use crate::Bind;
+use crate::clock::{self, Sent, Transmit, stamp};
 use crate::icmp::IcmpV6Packet;
+use crate::icmp::icmp6::HEADER_SIZE;
+use super::mode::Mode;
 use super::probe::Probe;
+use super::reply::Packet;
+use super::socket::{IPV6_RECVERR, Socket, deferred, offender, stamped};
 use super::state::State;
 
 pub struct Sock6 {
-    recv: JoinHandle<()>,
-    sock: Mutex<Arc<RawSocket>>
+    recv:  JoinHandle<()>,
+    errq:  JoinHandle<()>,
+    xmit:  Arc<Transmit>,
+    sock:  Mutex<Arc<Socket>>,
+    ident: Option<u16>,
 }
 
 impl Sock6 {
-    pub async fn new(bind: &Bind, state: Arc<State>) -> Result<Self> {
-        let raw   = Type::raw();
+    pub async fn new(bind: &Bind, mode: Mode, state: Arc<State>) -> Result<Self> {
         let icmp6 = Protocol::icmpv6();
 
-        let sock = Arc::new(RawSocket::new(Domain::ipv6(), raw, Some(icmp6))?);
-        sock.bind(bind.sa6()).await?;
-        let rx = sock.clone();
+        let (sock, mode) = mode.socket(Domain::ipv6(), icmp6)?;
+        let sock = Arc::new(sock);
+        sock.bind(bind.sa6())?;
+        clock::enable(&sock)?;
+
+        let ident = match mode {
+            Mode::Dgram => Some(sock.local_addr()?.port()),
+            _           => None,
+        };
+
+        let xmit = Arc::new(Transmit::default());
+        clock::transmit(&sock, &xmit)?;
+
+        let enable: c_int = 1;
+        sock.set_sockopt(Level::IPV6, Name::IPV6_RECVHOPLIMIT, &enable)?;
+        sock.set_sockopt(Level::IPV6, Name::from(IPV6_RECVTCLASS), &enable)?;
+
+        if mode == Mode::Dgram {
+            sock.set_sockopt(Level::IPV6, Name::from(IPV6_RECVERR), &enable)?;
+        }
+
+        let rx   = sock.clone();
+        let tx   = state.clone();
+        let sent = xmit.clone();
 
         let recv = tokio::spawn(async move {
-            match recv(rx, state).await {
+            match recv(rx, state, sent).await {
                 Ok(()) => debug!("recv finished"),
                 Err(e) => error!("recv failed: {}", e),
             }
         });
 
-        Ok(Self { recv, sock: Mutex::new(sock) })
+        let rx   = sock.clone();
+        let sent = xmit.clone();
+
+        let errq = tokio::spawn(async move {
+            match errors(rx, tx, sent).await {
+                Ok(()) => debug!("error queue finished"),
+                Err(e) => error!("error queue failed: {}", e),
+            }
+        });
+
+        Ok(Self { recv, errq, xmit, sock: Mutex::new(sock), ident })
+    }
+
+    pub fn ident(&self) -> Option<u16> {
+        self.ident
     }
 
-    pub async fn send(&self, probe: &Probe) -> Result<Instant> {
-        let mut pkt = [0u8; 64];
+    pub async fn send(&self, probe: &Probe) -> Result<Sent> {
+        let mut pkt = vec![0u8; HEADER_SIZE + probe.size];
 
         let pkt  = probe.encode(&mut pkt)?;
         let addr = SocketAddr::new(probe.addr, 0);
+
+        let tclass = c_int::from(probe.tos).to_ne_bytes();
+        let dfrag  = c_int::from(probe.df).to_ne_bytes();
+
+        let mut ctl = [0u8; 128];
+        let ctl = CMsg::encode(&mut ctl, &[
+            CMsg::Ipv6HopLimit(c_int::from(probe.ttl)),
+            Raw::from(IPPROTO_IPV6, IPV6_TCLASS, &tclass).into(),
+            Raw::from(IPPROTO_IPV6, IPV6_DONTFRAG, &dfrag).into(),
+        ])?;
+        let data = &[IoSlice::new(pkt)];
+
         let sock = self.sock.lock().await;
-        sock.send_to(pkt, &addr).await?;
+        let sent = self.xmit.sent();
+        if let Err(e) = sock.send_msg(&addr, data, ctl).await {
+            clock::transmit(&*sock, &self.xmit)?;
+            return Err(e.into());
+        }
 
-        Ok(Instant::now())
+        Ok(sent)
     }
 }
 
-async fn recv(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
-    let mut pkt = [0u8; 64];
+async fn recv(sock: Arc<Socket>, state: Arc<State>, xmit: Arc<Transmit>) -> Result<()> {
+    let mut pkt  = vec![0u8; PACKET_SIZE];
+    let mut ctl  = [0u8; 256];
+    let mut err  = vec![0u8; PACKET_SIZE];
+    let mut ectl = [0u8; 256];
+
     loop {
-        let (n, _) = sock.recv_from(&mut pkt).await?;
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, from) = match sock.recv_msg(iovec, &mut ctl).await {
+            Ok((n, from))          => (n, from),
+            Err(e) if deferred(&e) => continue,
+            Err(e)                 => return Err(e.into()),
+        };
 
-        let now = Instant::now();
-        let pkt = IcmpV6Packet::try_from(&pkt[..n])?;
+        flush(&sock, &state, &xmit, &mut err, &mut ectl);
 
-        if let IcmpV6Packet::EchoReply(echo) = pkt {
-            if let Ok(token) = echo.data.try_into() {
-                if let Some(tx) = state.remove(&token) {
-                    let _ = tx.send(now);
-                }
-            }
+        let (now, clock) = stamp(&ctl);
+        let raw = &pkt[..n];
+        let pkt = match IcmpV6Packet::try_from(raw) {
+            Ok(pkt) => pkt,
+            Err(e)  => {
+                debug!("invalid packet from {}: {}", from, e);
+                continue;
+            },
+        };
+
+        let (ttl, tos) = control(&ctl);
+
+        let (error, data, token) = match pkt {
+            IcmpV6Packet::EchoReply(echo) => (None, echo.data, echo.data.try_into().ok()),
+            IcmpV6Packet::Unreachable(..)
+                | IcmpV6Packet::PacketTooBig(..)
+                | IcmpV6Packet::HopLimitExceeded(..)
+                | IcmpV6Packet::ReassemblyTimeExceeded(..) => {
+                let quote = &raw[HEADER_SIZE..];
+                let token = match Probe::decode6(quote) {
+                    Ok((key, token)) => token.or_else(|| state.lookup(&key)),
+                    Err(_)           => None,
+                };
+                (Some((raw[0], raw[1])), quote, token)
+            },
+            _ => continue,
+        };
+
+        if let Some(tx) = token.and_then(|token| state.sender(&token)) {
+            let _ = tx.send(Packet {
+                addr:  from.ip(),
+                ttl:   ttl,
+                tos:   tos,
+                size:  n,
+                data:  data.to_vec(),
+                when:  now,
+                clock: clock,
+                error: error,
+            });
+        }
+    }
+}
+
+async fn errors(sock: Arc<Socket>, state: Arc<State>, xmit: Arc<Transmit>) -> Result<()> {
+    let mut pkt = vec![0u8; PACKET_SIZE];
+    let mut ctl = [0u8; 256];
+
+    loop {
+        let (n, len, dst) = sock.recv_err(&mut pkt, &mut ctl).await?;
+        error(&pkt[..n], &ctl[..len], dst, &state, &xmit);
+    }
+}
+
+fn flush(sock: &Socket, state: &State, xmit: &Transmit, pkt: &mut [u8], ctl: &mut [u8]) {
+    while let Ok((n, len, dst)) = sock.try_recv_err(pkt, ctl) {
+        error(&pkt[..n], &ctl[..len], dst, state, xmit);
+    }
+}
+
+fn error(pkt: &[u8], ctl: &[u8], dst: Option<IpAddr>, state: &State, xmit: &Transmit) {
+    if let (Some(id), Some(when)) = (stamped(ctl), clock::transmitted(ctl)) {
+        xmit.stamp(id, when);
+        return;
+    }
+
+    let (now, clock) = stamp(ctl);
+    let (ttl, tos)   = control(ctl);
+
+    let (addr, kind, code) = match offender(ctl) {
+        Some(offender) => offender,
+        None           => return,
+    };
+
+    let data  = pkt.get(HEADER_SIZE..).unwrap_or_default();
+    let token = data.try_into().ok().or_else(|| {
+        let (key, _) = Probe::echo6(dst?, pkt).ok()?;
+        state.lookup(&key)
+    });
+
+    if let Some(tx) = token.and_then(|token| state.sender(&token)) {
+        let _ = tx.send(Packet {
+            addr:  addr,
+            ttl:   ttl,
+            tos:   tos,
+            size:  pkt.len(),
+            data:  data.to_vec(),
+            when:  now,
+            clock: clock,
+            error: Some((kind, code)),
+        });
+    }
+}
+
+fn control(ctl: &[u8]) -> (u8, u8) {
+    let mut ttl = 0;
+    let mut tos = 0;
+
+    for msg in CMsg::decode(ctl) {
+        match msg {
+            CMsg::Ipv6HopLimit(limit)                 => ttl = limit as u8,
+            CMsg::Raw(raw) if raw.kind == IPV6_TCLASS => tos = tclass(raw.data),
+            _                                         => (),
         }
     }
+
+    (ttl, tos)
+}
+
+fn tclass(data: &[u8]) -> u8 {
+    match data.try_into() {
+        Ok(bytes) => c_int::from_ne_bytes(bytes) as u8,
+        Err(_)    => 0,
+    }
 }
 
 impl Drop for Sock6 {
     fn drop(&mut self) {
         self.recv.abort();
+        self.errq.abort();
     }
 }
+
+const PACKET_SIZE: usize = 65535;
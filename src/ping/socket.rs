@@ -0,0 +1,205 @@
+use std::io::{Error, IoSlice, IoSliceMut, Result};
+use std::mem::{size_of_val, zeroed};
+use std::slice::from_raw_parts;
+use std::convert::{TryFrom, TryInto};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::os::unix::io::AsRawFd;
+use libc::{AF_INET, AF_INET6, IPPROTO_IP, IPPROTO_IPV6, c_int};
+use raw_socket::control::Raw;
+use raw_socket::option::Opt;
+use raw_socket::prelude::*;
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+use crate::clock::Sockopt;
+
+pub struct Socket {
+    io: AsyncFd<RawSocket>,
+}
+
+impl Socket {
+    pub fn new(domain: Domain, kind: Type, protocol: Option<Protocol>) -> Result<Self> {
+        let sys = RawSocket::new(domain, kind, protocol)?;
+        sys.set_nonblocking(true)?;
+        let io  = AsyncFd::new(sys)?;
+        Ok(Self { io })
+    }
+
+    pub fn bind<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        self.io.get_ref().bind(addr)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.io.get_ref().local_addr()
+    }
+
+    pub async fn recv_msg(
+        &self,
+        data: &[IoSliceMut<'_>],
+        ctrl: &mut [u8],
+    ) -> Result<(usize, SocketAddr)> {
+        self.read(Interest::READABLE, |s| s.recv_msg(data, ctrl)).await
+    }
+
+    pub async fn recv_err(&self, data: &mut [u8], ctrl: &mut [u8]) -> Result<(usize, usize, Option<IpAddr>)> {
+        self.read(Interest::ERROR, |s| errqueue(s, data, ctrl)).await
+    }
+
+    pub fn try_recv_err(&self, data: &mut [u8], ctrl: &mut [u8]) -> Result<(usize, usize, Option<IpAddr>)> {
+        errqueue(self.io.get_ref(), data, ctrl)
+    }
+
+    pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> Result<usize> {
+        self.write(|s| s.send_to(buf, &addr)).await
+    }
+
+    pub async fn send_msg<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        data: &[IoSlice<'_>],
+        ctrl: &[u8],
+    ) -> Result<usize> {
+        self.write(|s| s.send_msg(&addr, data, ctrl)).await
+    }
+
+    pub fn set_sockopt<O: Opt>(&self, level: Level, name: Name, value: &O) -> Result<()> {
+        self.io.get_ref().set_sockopt(level, name, value)
+    }
+
+    async fn read<F: FnMut(&RawSocket) -> Result<R>, R>(&self, interest: Interest, mut f: F) -> Result<R> {
+        loop {
+            let mut guard = self.io.ready(interest).await?;
+            match guard.try_io(|inner| f(inner.get_ref())) {
+                Ok(r)  => return r,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    async fn write<F: FnMut(&RawSocket) -> Result<R>, R>(&self, mut f: F) -> Result<R> {
+        loop {
+            let mut guard = self.io.writable().await?;
+            match guard.try_io(|inner| f(inner.get_ref())) {
+                Ok(r)  => return r,
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+impl Sockopt for Socket {
+    fn set_sockopt<O: Opt>(&self, level: Level, name: Name, value: &O) -> Result<()> {
+        Socket::set_sockopt(self, level, name, value)
+    }
+}
+
+pub fn offender(ctl: &[u8]) -> Option<(IpAddr, u8, u8)> {
+    CMsg::decode(ctl).find_map(|msg| {
+        match msg {
+            CMsg::Raw(Raw { level: IPPROTO_IP,   kind: IP_RECVERR,   data }) => extended(data),
+            CMsg::Raw(Raw { level: IPPROTO_IPV6, kind: IPV6_RECVERR, data }) => extended(data),
+            _                                                                => None,
+        }
+    })
+}
+
+pub fn stamped(ctl: &[u8]) -> Option<u32> {
+    CMsg::decode(ctl).find_map(|msg| {
+        match msg {
+            CMsg::Raw(Raw { level: IPPROTO_IP,   kind: IP_RECVERR,   data }) => transmitted(data),
+            CMsg::Raw(Raw { level: IPPROTO_IPV6, kind: IPV6_RECVERR, data }) => transmitted(data),
+            _                                                                => None,
+        }
+    })
+}
+
+pub fn deferred(e: &Error) -> bool {
+    matches!(e.raw_os_error(), Some(code) if DEFERRED.contains(&code))
+}
+
+fn extended(data: &[u8]) -> Option<(IpAddr, u8, u8)> {
+    let (err, addr) = (data.get(..EXTENDED_SIZE)?, &data[EXTENDED_SIZE..]);
+
+    if err[4] != ORIGIN_ICMP && err[4] != ORIGIN_ICMP6 {
+        return None;
+    }
+
+    Some((address(addr)?, err[5], err[6]))
+}
+
+fn address(addr: &[u8]) -> Option<IpAddr> {
+    let family = u16::from_ne_bytes(addr.get(..2)?.try_into().ok()?);
+    match c_int::from(family) {
+        AF_INET  => Some(IpAddr::from(<[u8; 4]>::try_from(addr.get(4..8)?).ok()?)),
+        AF_INET6 => Some(IpAddr::from(<[u8; 16]>::try_from(addr.get(8..24)?).ok()?)),
+        _        => None,
+    }
+}
+
+fn transmitted(data: &[u8]) -> Option<u32> {
+    let err = data.get(..EXTENDED_SIZE)?;
+
+    if err[4] != ORIGIN_TIMESTAMPING {
+        return None;
+    }
+
+    Some(u32::from_ne_bytes(err[12..16].try_into().ok()?))
+}
+
+#[cfg(target_os = "linux")]
+fn errqueue(sock: &RawSocket, data: &mut [u8], ctrl: &mut [u8]) -> Result<(usize, usize, Option<IpAddr>)> {
+    unsafe {
+        let mut addr: libc::sockaddr_storage = zeroed();
+
+        let mut iov: libc::iovec = zeroed();
+        iov.iov_base = data.as_mut_ptr() as *mut _;
+        iov.iov_len  = data.len();
+
+        let mut msg: libc::msghdr = zeroed();
+        msg.msg_name       = &mut addr as *mut _ as *mut _;
+        msg.msg_namelen    = size_of_val(&addr) as _;
+        msg.msg_iov        = &mut iov;
+        msg.msg_iovlen     = 1;
+        msg.msg_control    = ctrl.as_mut_ptr() as *mut _;
+        msg.msg_controllen = ctrl.len() as _;
+
+        match libc::recvmsg(sock.as_raw_fd(), &mut msg, libc::MSG_ERRQUEUE) {
+            n if n >= 0 => {
+                let name = from_raw_parts(&addr as *const _ as *const u8, msg.msg_namelen as usize);
+                Ok((n as usize, msg.msg_controllen as usize, address(name)))
+            },
+            _ => Err(Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn errqueue(_sock: &RawSocket, _data: &mut [u8], _ctrl: &mut [u8]) -> Result<(usize, usize, Option<IpAddr>)> {
+    Err(Error::from(std::io::ErrorKind::Unsupported))
+}
+
+#[cfg(target_os = "linux")]
+pub const IP_RECVERR: c_int = libc::IP_RECVERR;
+
+#[cfg(target_os = "linux")]
+pub const IPV6_RECVERR: c_int = libc::IPV6_RECVERR;
+
+#[cfg(not(target_os = "linux"))]
+pub const IP_RECVERR: c_int = -1;
+
+#[cfg(not(target_os = "linux"))]
+pub const IPV6_RECVERR: c_int = -1;
+
+const DEFERRED: &[c_int] = &[
+    libc::EACCES,
+    libc::ECONNREFUSED,
+    libc::EHOSTUNREACH,
+    libc::EMSGSIZE,
+    libc::ENETUNREACH,
+    libc::ENOPROTOOPT,
+    libc::EPROTO,
+];
+
+const EXTENDED_SIZE:       usize = 16;
+const ORIGIN_ICMP:         u8    = 2;
+const ORIGIN_ICMP6:        u8    = 3;
+const ORIGIN_TIMESTAMPING: u8    = 4;
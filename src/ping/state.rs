@@ -1,53 +1,63 @@
 use std::collections::HashMap;
-use std::future::Future;
-use std::pin::Pin;
-use std::task::{Context, Poll};
-use std::time::Instant;
-use futures::ready;
 use parking_lot::Mutex;
-use tokio::sync::oneshot::{Receiver, Sender, channel, error::RecvError};
-use super::probe::Token;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use super::probe::{Key, Token};
+use super::reply::Packet;
 
 #[derive(Default)]
-pub struct State(Mutex<HashMap<Token, Sender<Instant>>>);
+pub struct State {
+    state: Mutex<HashMap<Token, UnboundedSender<Packet>>>,
+    ident: Mutex<HashMap<Key, Token>>,
+}
 
 pub struct Lease<'s> {
     state: &'s State,
-    rx:    Receiver<Instant>,
+    rx:    UnboundedReceiver<Packet>,
+    key:   Key,
     token: Token,
+    seen:  bool,
 }
 
 impl State {
-    pub fn insert(&self, token: Token) -> Lease<'_> {
-        let (tx, rx) = channel();
-        self.0.lock().insert(token, tx);
-        Lease::new(self, rx, token)
+    pub fn insert(&self, key: Key, token: Token) -> Lease<'_> {
+        let (tx, rx) = unbounded_channel();
+        self.state.lock().insert(token, tx);
+        self.ident.lock().insert(key, token);
+        Lease::new(self, rx, key, token)
+    }
+
+    pub fn lookup(&self, key: &Key) -> Option<Token> {
+        self.ident.lock().get(key).copied()
     }
 
-    pub fn remove(&self, token: &Token) -> Option<Sender<Instant>> {
-        self.0.lock().remove(token)
+    pub fn sender(&self, token: &Token) -> Option<UnboundedSender<Packet>> {
+        self.state.lock().get(token).cloned()
+    }
+
+    pub fn remove(&self, token: &Token) -> Option<UnboundedSender<Packet>> {
+        self.state.lock().remove(token)
     }
 }
 
 impl<'s> Lease<'s> {
-    fn new(state: &'s State, rx: Receiver<Instant>, token: Token) -> Self {
-        Self { state, rx, token }
+    fn new(state: &'s State, rx: UnboundedReceiver<Packet>, key: Key, token: Token) -> Self {
+        Self { state, rx, key, token, seen: false }
+    }
+
+    pub async fn recv(&mut self) -> Option<Packet> {
+        let pkt = self.rx.recv().await;
+        self.seen |= pkt.is_some();
+        pkt
+    }
+
+    pub fn seen(&self) -> bool {
+        self.seen
     }
 }
 
 impl Drop for Lease<'_> {
     fn drop(&mut self) {
         self.state.remove(&self.token);
-    }
-}
-
-impl Future for Lease<'_> {
-    type Output = Result<Instant, RecvError>;
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match ready!(Pin::new(&mut self.rx).poll(cx)) {
-            Ok(time) => Poll::Ready(Ok(time)),
-            Err(e)   => Poll::Ready(Err(e)),
-        }
+        self.state.ident.lock().remove(&self.key);
     }
 }
@@ -0,0 +1,397 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+use super::reply::Reply;
+
+#[derive(Clone, Debug, Default)]
+pub struct PingStats {
+    sent:   usize,
+    dups:   usize,
+    late:   usize,
+    recv:   usize,
+    mean:   f64,
+    m2:     f64,
+    min:    Option<Duration>,
+    max:    Option<Duration>,
+    last:   Option<Duration>,
+    jitter: f64,
+    window: usize,
+    recent: VecDeque<Option<Duration>>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PingSummary {
+    pub sent:       usize,
+    pub received:   usize,
+    pub duplicates: usize,
+    pub late:       usize,
+    pub loss:       f64,
+    pub last:       Option<Duration>,
+    pub min:        Option<Duration>,
+    pub avg:        Option<Duration>,
+    pub max:        Option<Duration>,
+    pub mdev:       Option<Duration>,
+    pub jitter:     Option<Duration>,
+}
+
+impl PingStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn windowed(window: usize) -> Self {
+        Self { window, ..Self::default() }
+    }
+
+    pub fn update(&mut self, rtt: Option<Duration>) {
+        self.sent += 1;
+
+        let limit = match self.window {
+            0 => SAMPLES,
+            n => n,
+        };
+
+        self.recent.push_back(rtt);
+        if self.recent.len() > limit {
+            let rtt = self.recent.pop_front().flatten();
+            if self.window > 0 {
+                self.evict(rtt);
+            }
+        }
+
+        let rtt = match rtt {
+            Some(rtt) => rtt,
+            None      => return,
+        };
+
+        let secs = rtt.as_secs_f64();
+
+        self.recv += 1;
+
+        let delta  = secs - self.mean;
+        self.mean += delta / self.recv as f64;
+        self.m2   += delta * (secs - self.mean);
+
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+
+        if let Some(last) = self.last {
+            self.jitter += diff(rtt, last).as_secs_f64();
+        }
+
+        self.last = Some(rtt);
+    }
+
+    pub fn record(&mut self, reply: &Reply) {
+        match reply {
+            Reply::Echo(echo) if echo.dup  => self.dups += 1,
+            Reply::Echo(echo) if echo.late => self.late += 1,
+            reply                          => self.update(reply.rtt()),
+        }
+    }
+
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    pub fn received(&self) -> usize {
+        self.recv
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.dups
+    }
+
+    pub fn late(&self) -> usize {
+        self.late
+    }
+
+    pub fn loss(&self) -> f64 {
+        match self.sent {
+            0    => 0.0,
+            sent => (sent - self.received()) as f64 * 100.0 / sent as f64,
+        }
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.last
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        match self.received() {
+            0 => None,
+            _ => Some(Duration::from_secs_f64(self.mean.max(0.0))),
+        }
+    }
+
+    pub fn mdev(&self) -> Option<Duration> {
+        match self.received() {
+            0 => None,
+            n => Some(Duration::from_secs_f64((self.m2 / n as f64).max(0.0).sqrt())),
+        }
+    }
+
+    pub fn jitter(&self) -> Option<Duration> {
+        match self.received() {
+            0 | 1 => None,
+            n     => Some(Duration::from_secs_f64(self.jitter.max(0.0) / (n - 1) as f64)),
+        }
+    }
+
+    pub fn summary(&self) -> PingSummary {
+        PingSummary {
+            sent:       self.sent,
+            received:   self.recv,
+            duplicates: self.dups,
+            late:       self.late,
+            loss:       self.loss(),
+            last:       self.last,
+            min:        self.min,
+            avg:        self.avg(),
+            max:        self.max,
+            mdev:       self.mdev(),
+            jitter:     self.jitter(),
+        }
+    }
+
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        self.percentiles(&[p])[0]
+    }
+
+    pub fn percentiles(&self, ps: &[f64]) -> Vec<Option<Duration>> {
+        let mut rtts = self.rtts().collect::<Vec<_>>();
+        rtts.sort_unstable();
+
+        ps.iter().map(|&p| {
+            if rtts.is_empty() || !(0.0..=100.0).contains(&p) {
+                return None;
+            }
+            let rank = (p / 100.0 * rtts.len() as f64).ceil() as usize;
+            Some(rtts[rank.saturating_sub(1)])
+        }).collect()
+    }
+
+    pub fn rtts(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.recent.iter().flatten().copied()
+    }
+
+    fn evict(&mut self, rtt: Option<Duration>) {
+        self.sent -= 1;
+
+        let rtt = match rtt {
+            Some(rtt) => rtt,
+            None      => return,
+        };
+
+        self.recv -= 1;
+
+        if self.recv == 0 {
+            self.mean   = 0.0;
+            self.m2     = 0.0;
+            self.jitter = 0.0;
+            self.min    = None;
+            self.max    = None;
+            self.last   = None;
+            return;
+        }
+
+        let next = self.rtts().next();
+        if let Some(next) = next {
+            self.jitter -= diff(rtt, next).as_secs_f64();
+        }
+
+        let secs  = rtt.as_secs_f64();
+        let delta = secs - self.mean;
+        self.mean -= delta / self.recv as f64;
+        self.m2   -= delta * (secs - self.mean);
+
+        if Some(rtt) == self.min || Some(rtt) == self.max {
+            self.min = self.rtts().min();
+            self.max = self.rtts().max();
+        }
+    }
+}
+
+impl Extend<Option<Duration>> for PingStats {
+    fn extend<T: IntoIterator<Item = Option<Duration>>>(&mut self, iter: T) {
+        for rtt in iter {
+            self.update(rtt);
+        }
+    }
+}
+
+impl fmt::Display for PingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sent = self.sent;
+        let recv = self.received();
+        let loss = self.loss();
+
+        write!(f, "{} packets transmitted, {} received, ", sent, recv)?;
+
+        if self.dups > 0 {
+            write!(f, "+{} duplicates, ", self.dups)?;
+        }
+
+        if self.late > 0 {
+            write!(f, "+{} late, ", self.late)?;
+        }
+
+        write!(f, "{:.0}% packet loss", loss)?;
+
+        if let (Some(min), Some(avg), Some(max), Some(mdev)) = (self.min, self.avg(), self.max, self.mdev()) {
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            write!(f, "\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms", ms(min), ms(avg), ms(max), ms(mdev))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn diff(a: Duration, b: Duration) -> Duration {
+    match a > b {
+        true  => a - b,
+        false => b - a,
+    }
+}
+
+const SAMPLES: usize = 1024;
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+    use crate::Clock;
+    use crate::clock::Timestamp;
+    use super::super::reply::{Echo, IcmpError, Reply};
+    use super::*;
+
+    #[test]
+    fn record_accounting() {
+        let mut stats = PingStats::new();
+
+        stats.record(&echo(ms(10), false, false));
+        stats.record(&echo(ms(20), true,  false));
+        stats.record(&echo(ms(30), false, true));
+        stats.record(&error());
+        stats.record(&Reply::Timeout(4));
+
+        assert_eq!(3,    stats.sent());
+        assert_eq!(1,    stats.received());
+        assert_eq!(1,    stats.duplicates());
+        assert_eq!(1,    stats.late());
+        assert_eq!(None, stats.jitter());
+        assert_close(200.0 / 3.0, stats.loss());
+        assert_eq!(Some(ms(10)), stats.last());
+    }
+
+    #[test]
+    fn update_welford() {
+        let mut stats = PingStats::new();
+        stats.extend(vec![Some(ms(10)), None, Some(ms(20)), Some(ms(30)), Some(ms(40))]);
+
+        assert_eq!(5, stats.sent());
+        assert_eq!(4, stats.received());
+        assert_close(20.0, stats.loss());
+
+        assert_eq!(Some(ms(10)), stats.min());
+        assert_eq!(Some(ms(40)), stats.max());
+        assert_eq!(Some(ms(40)), stats.last());
+
+        assert_close(25.0,          millis(stats.avg()));
+        assert_close(125f64.sqrt(), millis(stats.mdev()));
+        assert_close(10.0,          millis(stats.jitter()));
+    }
+
+    #[test]
+    fn window_eviction() {
+        let mut stats = PingStats::windowed(3);
+        stats.extend(vec![Some(ms(10)), None, Some(ms(20)), Some(ms(30)), Some(ms(40))]);
+
+        assert_eq!(3, stats.sent());
+        assert_eq!(3, stats.received());
+        assert_close(0.0, stats.loss());
+
+        assert_eq!(Some(ms(20)), stats.min());
+        assert_eq!(Some(ms(40)), stats.max());
+        assert_eq!(vec![ms(20), ms(30), ms(40)], stats.rtts().collect::<Vec<_>>());
+
+        assert_close(30.0,                  millis(stats.avg()));
+        assert_close((200f64 / 3.0).sqrt(), millis(stats.mdev()));
+        assert_close(10.0,                  millis(stats.jitter()));
+
+        stats.extend(vec![None, None, None]);
+
+        assert_eq!(3,    stats.sent());
+        assert_eq!(0,    stats.received());
+        assert_eq!(None, stats.avg());
+        assert_eq!(None, stats.min());
+        assert_eq!(None, stats.max());
+        assert_close(100.0, stats.loss());
+    }
+
+    #[test]
+    fn percentile_edges() {
+        let mut stats = PingStats::new();
+        assert_eq!(None, stats.percentile(50.0));
+
+        stats.extend(vec![Some(ms(40)), Some(ms(10)), None, Some(ms(30)), Some(ms(20))]);
+
+        assert_eq!(Some(ms(10)), stats.percentile(0.0));
+        assert_eq!(Some(ms(10)), stats.percentile(25.0));
+        assert_eq!(Some(ms(20)), stats.percentile(26.0));
+        assert_eq!(Some(ms(20)), stats.percentile(50.0));
+        assert_eq!(Some(ms(40)), stats.percentile(90.0));
+        assert_eq!(Some(ms(40)), stats.percentile(100.0));
+        assert_eq!(None,         stats.percentile(-1.0));
+        assert_eq!(None,         stats.percentile(100.5));
+    }
+
+    fn echo(rtt: Duration, dup: bool, late: bool) -> Reply {
+        Reply::Echo(Echo {
+            seq:     0,
+            addr:    ADDR,
+            ttl:     64,
+            tos:     0,
+            size:    64,
+            rtt:     rtt,
+            dup:     dup,
+            late:    late,
+            corrupt: false,
+            clock:   CLOCK,
+        })
+    }
+
+    fn error() -> Reply {
+        Reply::Error(IcmpError {
+            seq:   0,
+            addr:  ADDR,
+            kind:  3,
+            code:  1,
+            rtt:   ms(1),
+            clock: CLOCK,
+        })
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn millis(rtt: Option<Duration>) -> f64 {
+        rtt.unwrap().as_secs_f64() * 1000.0
+    }
+
+    fn assert_close(expect: f64, actual: f64) {
+        assert!((expect - actual).abs() < 1e-3, "{} != {}", expect, actual);
+    }
+
+    const ADDR:  IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const CLOCK: Clock  = Clock { send: Timestamp::User, recv: Timestamp::User };
+}
@@ -0,0 +1,26 @@
+use std::convert::TryFrom;
+use std::future::Future;
+use std::net::IpAddr;
+use anyhow::Result;
+use log::{debug, error};
+use raw_socket::tokio::prelude::*;
+use tokio::task::JoinHandle;
+
+pub fn spawn<F: Future<Output = Result<()>> + Send + 'static>(name: &'static str, future: F) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        match future.await {
+            Ok(()) => debug!("{} finished", name),
+            Err(e) => error!("{} failed: {}", name, e),
+        }
+    })
+}
+
+pub fn pktinfo(ctl: &[u8]) -> (Option<IpAddr>, Option<u8>) {
+    CMsg::decode(ctl).fold((None, None), |(dst, hops), msg| {
+        match msg {
+            CMsg::Ipv6PktInfo(info)   => (Some(info.addr().into()), hops),
+            CMsg::Ipv6HopLimit(limit) => (dst, u8::try_from(limit).ok()),
+            _                         => (dst, hops),
+        }
+    })
+}
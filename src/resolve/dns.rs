@@ -0,0 +1,309 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use futures::future::{BoxFuture, FutureExt};
+use rand::random;
+use tokio::net::UdpSocket;
+use tokio::time::timeout_at;
+use super::Resolver;
+
+#[derive(Clone, Debug)]
+pub struct Dns {
+    pub server:   SocketAddr,
+    pub timeout:  Duration,
+    pub attempts: usize,
+}
+
+impl Dns {
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server:   server,
+            timeout:  Duration::from_secs(2),
+            attempts: 2,
+        }
+    }
+
+    pub fn system() -> Result<Self> {
+        let conf = fs::read_to_string(RESOLV_CONF)?;
+
+        let server = conf.lines().find_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("nameserver"), Some(addr)) => addr.parse::<IpAddr>().ok(),
+                _                                => None,
+            }
+        }).ok_or_else(|| anyhow!("no nameserver in {}", RESOLV_CONF))?;
+
+        Ok(Self::new(SocketAddr::new(server, PORT)))
+    }
+
+    pub async fn ptr(&self, addr: IpAddr) -> Result<Option<String>> {
+        let bind = match self.server {
+            SocketAddr::V4(..) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(..) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+
+        let sock = UdpSocket::bind(bind).await?;
+        sock.connect(self.server).await?;
+
+        let id    = random();
+        let query = query(id, &arpa(addr))?;
+        let mut buf = [0u8; 1500];
+
+        for _ in 0..self.attempts.max(1) {
+            sock.send(&query).await?;
+
+            let deadline = Instant::now() + self.timeout;
+
+            while let Ok(n) = timeout_at(deadline.into(), sock.recv(&mut buf)).await {
+                if let Some(answer) = answer(&buf[..n?], id) {
+                    return answer;
+                }
+            }
+        }
+
+        Err(anyhow!("DNS query for {} timed out", addr))
+    }
+}
+
+impl Resolver for Dns {
+    fn lookup(&self, addr: IpAddr) -> BoxFuture<'_, Result<Option<String>>> {
+        self.ptr(addr).boxed()
+    }
+}
+
+fn arpa(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, c, d] = addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        },
+        IpAddr::V6(addr) => {
+            let mut name = String::with_capacity(72);
+            for byte in addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0f, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        },
+    }
+}
+
+fn query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut pkt = Vec::with_capacity(HEADER_SIZE + name.len() + 6);
+    pkt.extend_from_slice(&id.to_be_bytes());
+    pkt.extend_from_slice(&RD.to_be_bytes());
+    pkt.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.split('.') {
+        pkt.push(u8::try_from(label.len())?);
+        pkt.extend_from_slice(label.as_bytes());
+    }
+    pkt.push(0);
+
+    pkt.extend_from_slice(&PTR.to_be_bytes());
+    pkt.extend_from_slice(&IN.to_be_bytes());
+
+    Ok(pkt)
+}
+
+fn answer(pkt: &[u8], id: u16) -> Option<Result<Option<String>>> {
+    if pkt.len() < HEADER_SIZE || u16::from_be_bytes([pkt[0], pkt[1]]) != id || pkt[2] & 0x80 == 0 {
+        return None;
+    }
+
+    Some(match pkt[3] & 0x0f {
+        NOERROR  => records(pkt),
+        NXDOMAIN => Ok(None),
+        rcode    => Err(anyhow!("DNS server returned rcode {}", rcode)),
+    })
+}
+
+fn records(pkt: &[u8]) -> Result<Option<String>> {
+    let qdcount = u16::from_be_bytes([pkt[4], pkt[5]]);
+    let ancount = u16::from_be_bytes([pkt[6], pkt[7]]);
+
+    let mut pos = HEADER_SIZE;
+
+    for _ in 0..qdcount {
+        pos = name(pkt, pos)?.1 + 4;
+    }
+
+    for _ in 0..ancount {
+        pos = name(pkt, pos)?.1;
+
+        let head = pkt.get(pos..pos + 10).ok_or_else(short)?;
+        let kind = u16::from_be_bytes([head[0], head[1]]);
+        let len  = usize::from(u16::from_be_bytes([head[8], head[9]]));
+
+        pos += 10;
+
+        if kind == PTR {
+            return Ok(Some(name(pkt, pos)?.0));
+        }
+
+        pos += len;
+    }
+
+    Ok(None)
+}
+
+fn name(pkt: &[u8], mut pos: usize) -> Result<(String, usize)> {
+    let mut name = String::new();
+    let mut next = None;
+
+    for _ in 0..MAX_JUMPS {
+        let len = *pkt.get(pos).ok_or_else(short)?;
+
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                return Ok((name, next.unwrap_or(pos + 1)));
+            },
+            0x00 => {
+                let label = pkt.get(pos + 1..pos + 1 + usize::from(len)).ok_or_else(short)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                pos += 1 + usize::from(len);
+            },
+            0xc0 => {
+                let low = *pkt.get(pos + 1).ok_or_else(short)?;
+                next = next.or(Some(pos + 2));
+                pos  = usize::from(u16::from_be_bytes([len & 0x3f, low]));
+            },
+            _    => return Err(anyhow!("invalid DNS label")),
+        }
+    }
+
+    Err(anyhow!("DNS name too long"))
+}
+
+fn short() -> anyhow::Error {
+    anyhow!("short DNS response")
+}
+
+const RESOLV_CONF: &str  = "/etc/resolv.conf";
+const PORT:        u16   = 53;
+const HEADER_SIZE: usize = 12;
+const MAX_JUMPS:   usize = 128;
+
+const RD:  u16 = 0x0100;
+const PTR: u16 = 12;
+const IN:  u16 = 1;
+
+const NOERROR:  u8 = 0;
+const NXDOMAIN: u8 = 3;
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use std::iter::once;
+    use std::net::IpAddr;
+    use super::*;
+
+    #[test]
+    fn arpa_names() {
+        assert_eq!(NAME, arpa(IpAddr::from([192, 0, 2, 1])));
+        assert_eq!(
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa",
+            arpa(IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1])),
+        );
+    }
+
+    #[test]
+    fn answer_ptr() {
+        let pkt = response(NOERROR, &[
+            record(CNAME, &[labels("alias.example"), vec![0]].concat()),
+            record(PTR,   &[labels("router"), vec![0xc0, 0x0c]].concat()),
+        ]);
+
+        let name = answer(&pkt, ID).unwrap().unwrap();
+        assert_eq!(Some(format!("router.{}", NAME)), name);
+    }
+
+    #[test]
+    fn answer_filter() {
+        let mut pkt = response(NOERROR, &[]);
+        assert!(answer(&pkt, ID + 1).is_none());
+        assert!(answer(&pkt[..HEADER_SIZE - 1], ID).is_none());
+
+        pkt[2] &= 0x7f;
+        assert!(answer(&pkt, ID).is_none());
+    }
+
+    #[test]
+    fn answer_rcode() {
+        assert_eq!(None, answer(&response(NOERROR,  &[]), ID).unwrap().unwrap());
+        assert_eq!(None, answer(&response(NXDOMAIN, &[]), ID).unwrap().unwrap());
+        assert!(answer(&response(SERVFAIL, &[]), ID).unwrap().is_err());
+    }
+
+    #[test]
+    fn pointer_loop() {
+        let mut pkt = response(NOERROR, &[record(PTR, &[0xc0, 0x00])]);
+        let at = pkt.len() - 2;
+        pkt[at + 1] = u8::try_from(at).unwrap();
+        assert!(answer(&pkt, ID).unwrap().is_err());
+
+        let mut pkt = response(NOERROR, &[record(PTR, &[0xc0, 0x00, 0xc0, 0x00])]);
+        let at = pkt.len() - 4;
+        pkt[at + 1] = u8::try_from(at + 2).unwrap();
+        pkt[at + 3] = u8::try_from(at).unwrap();
+        assert!(answer(&pkt, ID).unwrap().is_err());
+    }
+
+    #[test]
+    fn pointer_range() {
+        let pkt = response(NOERROR, &[record(PTR, &[0xc0, 0xff])]);
+        assert!(answer(&pkt, ID).unwrap().is_err());
+
+        let pkt = response(NOERROR, &[record(PTR, &[0xff, 0xff])]);
+        assert!(answer(&pkt, ID).unwrap().is_err());
+
+        let pkt = response(NOERROR, &[record(PTR, &[0x40, 0x00])]);
+        assert!(answer(&pkt, ID).unwrap().is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let pkt = response(NOERROR, &[
+            record(CNAME, &[labels("alias.example"), vec![0]].concat()),
+            record(PTR,   &[labels("router"), vec![0xc0, 0x0c]].concat()),
+        ]);
+
+        for n in HEADER_SIZE..pkt.len() {
+            assert!(answer(&pkt[..n], ID).unwrap().is_err(), "{} bytes", n);
+        }
+    }
+
+    fn response(rcode: u8, answers: &[Vec<u8>]) -> Vec<u8> {
+        let mut pkt = query(ID, NAME).unwrap();
+        pkt[2] |= 0x80;
+        pkt[3] |= rcode;
+        pkt[6..8].copy_from_slice(&u16::try_from(answers.len()).unwrap().to_be_bytes());
+        answers.iter().for_each(|answer| pkt.extend_from_slice(answer));
+        pkt
+    }
+
+    fn record(kind: u16, data: &[u8]) -> Vec<u8> {
+        let mut rr = vec![0xc0, 0x0c];
+        rr.extend_from_slice(&kind.to_be_bytes());
+        rr.extend_from_slice(&IN.to_be_bytes());
+        rr.extend_from_slice(&300u32.to_be_bytes());
+        rr.extend_from_slice(&u16::try_from(data.len()).unwrap().to_be_bytes());
+        rr.extend_from_slice(data);
+        rr
+    }
+
+    fn labels(name: &str) -> Vec<u8> {
+        name.split('.').flat_map(|label| once(label.len() as u8).chain(label.bytes())).collect()
+    }
+
+    const ID:       u16  = 0x1234;
+    const NAME:     &str = "1.2.0.192.in-addr.arpa";
+    const CNAME:    u16  = 5;
+    const SERVFAIL: u8   = 2;
+}
@@ -0,0 +1,13 @@
+pub use dns::Dns;
+pub use names::Names;
+
+use std::net::IpAddr;
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+pub trait Resolver: Send + Sync {
+    fn lookup(&self, addr: IpAddr) -> BoxFuture<'_, Result<Option<String>>>;
+}
+
+mod dns;
+mod names;
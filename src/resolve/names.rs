@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use parking_lot::Mutex;
+use tokio::time::timeout;
+use crate::trace::Node;
+use super::Resolver;
+
+#[derive(Clone)]
+pub struct Names {
+    resolver: Arc<dyn Resolver>,
+    timeout:  Duration,
+    cache:    Arc<Mutex<HashMap<IpAddr, Entry>>>,
+}
+
+#[derive(Clone)]
+enum Entry {
+    Pending(Lookup),
+    Done(Option<String>, Instant),
+    Failed(Instant),
+}
+
+type Lookup = Shared<BoxFuture<'static, Option<String>>>;
+
+impl Names {
+    pub fn new<R: Resolver + 'static>(resolver: R, timeout: Duration) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            timeout:  timeout,
+            cache:    Default::default(),
+        }
+    }
+
+    pub async fn lookup(&self, addr: IpAddr) -> Option<String> {
+        let lookup = match self.cache.lock().entry(addr) {
+            Occupied(mut e) => match e.get() {
+                Entry::Pending(lookup)                       => lookup.clone(),
+                Entry::Done(name, until) if !expired(*until) => return name.clone(),
+                Entry::Failed(until) if !expired(*until)     => return None,
+                _                                            => {
+                    let lookup = self.resolve(addr);
+                    e.insert(Entry::Pending(lookup.clone()));
+                    lookup
+                },
+            },
+            Vacant(e)       => {
+                let lookup = self.resolve(addr);
+                e.insert(Entry::Pending(lookup.clone()));
+                lookup
+            },
+        };
+        lookup.await
+    }
+
+    pub async fn annotate(&self, mut node: Node) -> Node {
+        node.name = self.lookup(node.addr).await;
+        node
+    }
+
+    pub fn cached(&self, addr: IpAddr) -> Option<String> {
+        match self.cache.lock().get(&addr) {
+            Some(Entry::Done(name, until)) if !expired(*until) => name.clone(),
+            _                                                  => None,
+        }
+    }
+
+    pub fn prefetch(&self, addr: IpAddr) {
+        match self.cache.lock().get(&addr) {
+            Some(entry) if entry.expired() => (),
+            Some(_)                        => return,
+            None                           => (),
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            this.lookup(addr).await;
+        });
+    }
+
+    fn resolve(&self, addr: IpAddr) -> Lookup {
+        let this = self.clone();
+        async move {
+            let result = timeout(this.timeout, this.resolver.lookup(addr)).await;
+            let mut cache = this.cache.lock();
+            cache.retain(|_, entry| !entry.expired());
+            match result {
+                Ok(Ok(name)) => {
+                    cache.insert(addr, Entry::Done(name.clone(), Instant::now() + POSITIVE_TTL));
+                    name
+                },
+                _            => {
+                    cache.insert(addr, Entry::Failed(Instant::now() + NEGATIVE_TTL));
+                    None
+                },
+            }
+        }.boxed().shared()
+    }
+}
+
+impl Entry {
+    fn expired(&self) -> bool {
+        match self {
+            Entry::Pending(_)     => false,
+            Entry::Done(_, until) => expired(*until),
+            Entry::Failed(until)  => expired(*until),
+        }
+    }
+}
+
+fn expired(until: Instant) -> bool {
+    until <= Instant::now()
+}
+
+const POSITIVE_TTL: Duration = Duration::from_secs(3600);
+const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+    use anyhow::{anyhow, Result};
+    use futures::future::{BoxFuture, FutureExt};
+    use super::{Entry, Names, Resolver};
+
+    struct Failing(Arc<AtomicUsize>);
+
+    impl Resolver for Failing {
+        fn lookup(&self, _addr: IpAddr) -> BoxFuture<'_, Result<Option<String>>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            async { Err(anyhow!("failed")) }.boxed()
+        }
+    }
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Resolver for Counting {
+        fn lookup(&self, _addr: IpAddr) -> BoxFuture<'_, Result<Option<String>>> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(Some(format!("host{}.example", n))) }.boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn negative_cache() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let names = Names::new(Failing(count.clone()), Duration::from_secs(1));
+        let addr  = IpAddr::from([192, 0, 2, 1]);
+
+        assert_eq!(None, names.lookup(addr).await);
+        assert_eq!(None, names.lookup(addr).await);
+        names.prefetch(addr);
+        tokio::task::yield_now().await;
+
+        assert_eq!(None, names.cached(addr));
+        assert_eq!(1, count.load(Ordering::SeqCst));
+    }
+    #[tokio::test]
+    async fn positive_cache() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let names = Names::new(Counting(count.clone()), Duration::from_secs(1));
+        let addr  = IpAddr::from([192, 0, 2, 1]);
+        let name  = Some("host0.example".to_owned());
+
+        assert_eq!(name, names.lookup(addr).await);
+        assert_eq!(name, names.lookup(addr).await);
+        assert_eq!(name, names.cached(addr));
+        assert_eq!(1, count.load(Ordering::SeqCst));
+
+        names.cache.lock().insert(addr, Entry::Done(name, Instant::now()));
+
+        assert_eq!(None, names.cached(addr));
+        assert_eq!(Some("host1.example".to_owned()), names.lookup(addr).await);
+        assert_eq!(2, count.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn evict_expired() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let names = Names::new(Counting(count.clone()), Duration::from_secs(1));
+        let stale = IpAddr::from([192, 0, 2, 1]);
+        let addr  = IpAddr::from([192, 0, 2, 2]);
+
+        names.cache.lock().insert(stale, Entry::Done(None, Instant::now()));
+        names.lookup(addr).await;
+
+        assert!(!names.cache.lock().contains_key(&stale));
+        assert!(names.cache.lock().contains_key(&addr));
+    }
+}
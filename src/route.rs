@@ -24,6 +24,34 @@ impl RouteSocket {
         self.sock.connect(addr).await?;
         Ok(self.sock.local_addr()?.ip())
     }
+
+    pub async fn mtu(&mut self, addr: SocketAddr) -> Result<usize> {
+        self.source(addr).await?;
+        mtu(&self.sock, addr.ip())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn mtu(sock: &UdpSocket, addr: IpAddr) -> Result<usize> {
+    let (level, name) = match addr {
+        IpAddr::V4(..) => (libc::IPPROTO_IP,   libc::IP_MTU),
+        IpAddr::V6(..) => (libc::IPPROTO_IPV6, libc::IPV6_MTU),
+    };
+
+    unsafe {
+        let fd = sock.as_raw_fd();
+        let mut mtu: libc::c_int = 0;
+        let mut len = size_of::<libc::c_int>().try_into()?;
+        match libc::getsockopt(fd, level, name, &mut mtu as *mut _ as *mut _, &mut len) {
+            0 => Ok(mtu.try_into()?),
+            _ => Err(Error::last_os_error().into())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mtu(_sock: &UdpSocket, _addr: IpAddr) -> Result<usize> {
+    Err(anyhow::anyhow!("MTU query not supported"))
 }
 
 fn reset(sock: &UdpSocket) -> Result<()> {
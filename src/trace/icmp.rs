@@ -1,18 +1,17 @@
 use std::convert::TryFrom;
-use std::future::Future;
 use std::io::IoSliceMut;
 use std::sync::Arc;
-use std::time::Instant;
 use anyhow::Result;
-use etherparse::{IpNumber, Ipv4Header};
+use etherparse::{IpNumber, Ipv4Header, Ipv6Header};
 use libc::c_int;
-use log::{debug, error};
 use raw_socket::tokio::prelude::*;
 use tokio::task::JoinHandle;
 use crate::Bind;
+use crate::clock::{self, stamp};
 use crate::icmp::{icmp4, icmp6, IcmpV4Packet, IcmpV6Packet};
-use super::probe::{Key, Probe};
-use super::reply::Echo;
+use crate::recv::{pktinfo, spawn};
+use super::probe::{Ident, Key, Probe};
+use super::reply::{Echo, Kind};
 use super::state::State;
 
 pub struct Icmp {
@@ -35,9 +34,13 @@ impl Icmp {
         icmp4.bind(bind.sa4()).await?;
         icmp6.bind(bind.sa6()).await?;
 
+        clock::enable(&icmp4)?;
+        clock::enable(&icmp6)?;
+
         let enable: c_int = 1;
-        icmp4.set_sockopt(Level::IPV4, Name::IPV4_HDRINCL,     &enable)?;
-        icmp6.set_sockopt(Level::IPV6, Name::IPV6_RECVPKTINFO, &enable)?;
+        icmp4.set_sockopt(Level::IPV4, Name::IPV4_HDRINCL,      &enable)?;
+        icmp6.set_sockopt(Level::IPV6, Name::IPV6_RECVPKTINFO,  &enable)?;
+        icmp6.set_sockopt(Level::IPV6, Name::IPV6_RECVHOPLIMIT, &enable)?;
 
         let recv4 = spawn("recv4", recv4(icmp4.clone(), state.clone()));
         let recv6 = spawn("recv6", recv6(icmp6.clone(), state.clone()));
@@ -47,105 +50,125 @@ impl Icmp {
 }
 
 async fn recv4(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
-    let mut pkt = [0u8; 128];
+    let mut pkt = [0u8; 1500];
+    let mut ctl = [0u8; 128];
+
     loop {
-        let (n, from) = sock.recv_from(&mut pkt).await?;
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, from) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
 
-        let now = Instant::now();
+        let (now, clock) = stamp(&ctl);
         let pkt = Ipv4Header::from_slice(&pkt[..n])?;
 
         if let (ip @ Ipv4Header { protocol: ICMP, .. }, tail) = pkt {
             let icmp = IcmpV4Packet::try_from(tail)?;
 
-            if let IcmpV4Packet::TimeExceeded(pkt) = icmp {
-                if let Ok(key) = Probe::decode4(pkt) {
-                    if let Some(tx) = state.sender(&key) {
-                        let _ = tx.send(Echo(from.ip(), now, false));
-                    }
-                }
-            } else if let IcmpV4Packet::Unreachable(what) = icmp {
-                let pkt = match what {
-                    icmp4::Unreachable::Net(pkt)      => pkt,
-                    icmp4::Unreachable::Host(pkt)     => pkt,
-                    icmp4::Unreachable::Protocol(pkt) => pkt,
-                    icmp4::Unreachable::Port(pkt)     => pkt,
-                    icmp4::Unreachable::Other(_, pkt) => pkt,
-                };
-
-                if let Ok(key) = Probe::decode4(pkt) {
-                    if let Some(tx) = state.sender(&key) {
-                        let _ = tx.send(Echo(from.ip(), now, true));
-                    }
+            let quote = match &icmp {
+                IcmpV4Packet::TimeExceeded(pkt) => Some(*pkt),
+                IcmpV4Packet::Unreachable(what) => Some(match what {
+                    icmp4::Unreachable::Net(pkt)              => *pkt,
+                    icmp4::Unreachable::Host(pkt)             => *pkt,
+                    icmp4::Unreachable::Protocol(pkt)         => *pkt,
+                    icmp4::Unreachable::Port(pkt)             => *pkt,
+                    icmp4::Unreachable::Fragmentation(_, pkt) => *pkt,
+                    icmp4::Unreachable::Other(_, pkt)         => *pkt,
+                }),
+                _                               => None,
+            };
+
+            let mtu = match &icmp {
+                IcmpV4Packet::Unreachable(what) => what.mtu().map(u32::from),
+                _                               => None,
+            };
+
+            let echo = |quoted| Echo {
+                addr:   from.ip(),
+                when:   now,
+                kind:   Kind::ICMPv4(tail[0], tail[1]),
+                ttl:    Some(ip.time_to_live),
+                quoted: quoted,
+                size:   tail.len(),
+                mtu:    mtu,
+                ext:    icmp4::extensions(tail).unwrap_or_default(),
+                clock:  clock,
+            };
+
+            if let Some(pkt) = quote {
+                if let Ok(probe) = Probe::decode4(pkt) {
+                    state.deliver(&probe.key(), &probe.idents(), echo(quoted4(pkt)));
                 }
-            } else if let IcmpV4Packet::EchoReply(echo) = icmp {
+            } else if let IcmpV4Packet::EchoReply(reply) = icmp {
                 let src = ip.source.into();
                 let dst = ip.destination.into();
-                let key = Key::ICMP(dst, src, echo.id);
-
-                if let Some(tx) = state.sender(&key) {
-                    let _ = tx.send(Echo(from.ip(), now, true));
-                }
+                let key = Key::ICMP(dst, src, reply.id);
+                state.deliver(&key, &[Ident::ICMP(reply.seq)], echo(None));
             }
         }
     }
 }
 
 async fn recv6(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
-    let mut pkt = [0u8; 64];
-    let mut ctl = [0u8; 64];
+    let mut pkt = [0u8; 1500];
+    let mut ctl = [0u8; 128];
 
     loop {
         let iovec = &[IoSliceMut::new(&mut pkt)];
         let (n, from) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
 
-        let now = Instant::now();
-        let pkt = IcmpV6Packet::try_from(&pkt[..n])?;
-
-        if let IcmpV6Packet::HopLimitExceeded(pkt) = pkt {
-            if let Ok(key) = Probe::decode6(pkt) {
-                if let Some(tx) = state.sender(&key) {
-                    let _ = tx.send(Echo(from.ip(), now, false));
-                }
-            }
-        } else if let IcmpV6Packet::Unreachable(what) = pkt {
-            let pkt = match what {
-                icmp6::Unreachable::Address(pkt)  => pkt,
-                icmp6::Unreachable::Port(pkt)     => pkt,
-                icmp6::Unreachable::Other(_, pkt) => pkt,
-            };
-
-            if let Ok(key) = Probe::decode6(pkt) {
-                if let Some(tx) = state.sender(&key) {
-                    let _ = tx.send(Echo(from.ip(), now, false));
-                }
-            }
-        } else if let IcmpV6Packet::EchoReply(echo) = pkt {
-            let dst = CMsg::decode(&ctl).find_map(|msg| {
-                match msg {
-                    CMsg::Ipv6PktInfo(info) => Some(info.addr()),
-                    _                       => None,
-                }
-            });
-
-            if let Some(dst) = dst {
-                let dst = dst.into();
-                let key = Key::ICMP(dst, from.ip(), echo.id);
-
-                if let Some(tx) = state.sender(&key) {
-                    let _ = tx.send(Echo(from.ip(), now, true));
-                }
+        let (now, clock) = stamp(&ctl);
+        let (dst, hops) = pktinfo(&ctl);
+
+        let data = &pkt[..n];
+        let icmp = IcmpV6Packet::try_from(data)?;
+
+        let quote = match &icmp {
+            IcmpV6Packet::HopLimitExceeded(pkt) => Some(*pkt),
+            IcmpV6Packet::PacketTooBig(_, pkt)  => Some(*pkt),
+            IcmpV6Packet::Unreachable(what)     => Some(match what {
+                icmp6::Unreachable::Address(pkt)  => *pkt,
+                icmp6::Unreachable::Port(pkt)     => *pkt,
+                icmp6::Unreachable::Other(_, pkt) => *pkt,
+            }),
+            _                                   => None,
+        };
+
+        let mtu = match &icmp {
+            IcmpV6Packet::PacketTooBig(mtu, _) => Some(*mtu),
+            _                                  => None,
+        };
+
+        let echo = |quoted| Echo {
+            addr:   from.ip(),
+            when:   now,
+            kind:   Kind::ICMPv6(data[0], data[1]),
+            ttl:    hops,
+            quoted: quoted,
+            size:   data.len(),
+            mtu:    mtu,
+            ext:    icmp6::extensions(data).unwrap_or_default(),
+            clock:  clock,
+        };
+
+        if let Some(pkt) = quote {
+            if let Ok(probe) = Probe::decode6(pkt) {
+                state.deliver(&probe.key(), &probe.idents(), echo(quoted6(pkt)));
             }
+        } else if let (IcmpV6Packet::EchoReply(reply), Some(dst)) = (icmp, dst) {
+            let key = Key::ICMP(dst, from.ip(), reply.id);
+            state.deliver(&key, &[Ident::ICMP(reply.seq)], echo(None));
         }
     }
 }
 
-fn spawn<F: Future<Output = Result<()>> + Send + 'static>(name: &'static str, future: F) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        match future.await {
-            Ok(()) => debug!("{} finished", name),
-            Err(e) => error!("{} failed: {}", name, e),
-        }
-    })
+fn quoted4(pkt: &[u8]) -> Option<(u8, u8)> {
+    let (head, _) = Ipv4Header::from_slice(pkt).ok()?;
+    let tos = head.differentiated_services_code_point << 2 | head.explicit_congestion_notification;
+    Some((head.time_to_live, tos))
+}
+
+fn quoted6(pkt: &[u8]) -> Option<(u8, u8)> {
+    let (head, _) = Ipv6Header::from_slice(pkt).ok()?;
+    Some((head.hop_limit, head.traffic_class))
 }
 
 const ICMP: u8 = IpNumber::Icmp as u8;
@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::Entry;
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use crate::limit::ticks;
+use super::probe::{Probe, Probes, Protocol};
+use super::reply::Node;
+use super::trace::Tracer;
+
+#[derive(Debug)]
+pub struct Mda {
+    pub proto:      Protocol,
+    pub addr:       IpAddr,
+    pub confidence: f64,
+    pub flows:      u16,
+    pub limit:      u8,
+    pub expiry:     Duration,
+    pub interval:   Duration,
+    pub deadline:   Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub hops:  Vec<Hop>,
+    pub links: Vec<Link>,
+}
+
+#[derive(Debug)]
+pub struct Hop {
+    pub ttl:        u8,
+    pub interfaces: Vec<Interface>,
+    pub probes:     usize,
+    pub lost:       usize,
+}
+
+#[derive(Debug)]
+pub struct Interface {
+    pub addr:  IpAddr,
+    pub flows: Vec<u16>,
+    pub rtt:   Duration,
+    pub last:  bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Link {
+    pub ttl:   u8,
+    pub from:  IpAddr,
+    pub to:    IpAddr,
+    pub flows: Vec<u16>,
+}
+
+type Flows = BTreeMap<u16, Option<Node>>;
+
+impl Default for Mda {
+    fn default() -> Self {
+        Self {
+            proto:      Protocol::default(),
+            addr:       IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            confidence: 0.95,
+            flows:      256,
+            limit:      30,
+            expiry:     Duration::from_millis(250),
+            interval:   Duration::from_millis(50),
+            deadline:   None,
+        }
+    }
+}
+
+impl Tracer {
+    pub async fn mda(&self, mda: Mda) -> Result<Graph> {
+        let Mda { proto, addr, confidence, flows, limit, expiry, interval, deadline } = mda;
+
+        if let Protocol::TCP(..) = proto {
+            return Err(anyhow!("MDA requires ICMP or UDP probes"));
+        }
+
+        if !(0.0..1.0).contains(&confidence) {
+            return Err(anyhow!("confidence must be in [0, 1)"));
+        }
+
+        if let Protocol::UDP(port) = proto {
+            if port.checked_add(flows.saturating_sub(1)).is_none() {
+                return Err(anyhow!("{} flows exceed UDP port range from {}", flows, port));
+            }
+        }
+
+        let stride = (u32::from(u16::MAX) + 1) / u32::from(flows.max(1));
+        if stride <= 2 * u32::from(limit) {
+            return Err(anyhow!("{} flows leave too few sequence numbers for {} hops", flows, limit));
+        }
+
+        let source = self.reserve(proto, addr).await?;
+
+        let mut probes = HashMap::new();
+        let mut ticks  = ticks(interval, deadline).boxed();
+        let mut hops   = Vec::<Flows>::new();
+
+        let mut ctx = Context { source: &source, probes: &mut probes, ticks: &mut ticks, expiry, stride };
+
+        'ttl: for ttl in 1..=limit {
+            let mut hop = Flows::new();
+
+            for flow in 0..flows {
+                if enough(&hop, confidence) {
+                    break;
+                }
+
+                if !ctx.tick().await {
+                    break 'ttl;
+                }
+
+                hop.insert(flow, self.flow(&mut ctx, flow, ttl).await?);
+            }
+
+            if let Some(prev) = hops.last_mut() {
+                for &flow in hop.keys() {
+                    if prev.contains_key(&flow) {
+                        continue;
+                    }
+
+                    if !ctx.tick().await {
+                        break 'ttl;
+                    }
+
+                    prev.insert(flow, self.flow(&mut ctx, flow, ttl - 1).await?);
+                }
+            }
+
+            let done = hop.values().flatten().any(|node| {
+                node.last() || node.addr == addr
+            });
+
+            hops.push(hop);
+
+            if done {
+                break;
+            }
+        }
+
+        Ok(Graph::new(&hops))
+    }
+
+    async fn flow(&self, ctx: &mut Context<'_, '_>, flow: u16, ttl: u8) -> Result<Option<Node>> {
+        let probe = match ctx.probes.entry(flow) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e)   => {
+                let mut probe = ctx.source.paris(flow)?;
+                probe.sequence(u16::try_from(u32::from(flow) * ctx.stride + 1)?);
+                e.insert(probe)
+            },
+        };
+
+        self.once(probe, ttl, ctx.expiry).await
+    }
+}
+
+struct Context<'a, 'b> {
+    source: &'a Probes,
+    probes: &'b mut HashMap<u16, Probe>,
+    ticks:  &'b mut BoxStream<'static, Instant>,
+    expiry: Duration,
+    stride: u32,
+}
+
+impl Context<'_, '_> {
+    async fn tick(&mut self) -> bool {
+        self.ticks.next().await.is_some()
+    }
+}
+
+impl Graph {
+    fn new(hops: &[Flows]) -> Self {
+        let mut links = BTreeMap::<(u8, IpAddr, IpAddr), Vec<u16>>::new();
+
+        for (ttl, pair) in (2..).zip(hops.windows(2)) {
+            for (flow, node) in &pair[1] {
+                if let (Some(from), Some(to)) = (addr(pair[0].get(flow)), addr(Some(node))) {
+                    links.entry((ttl, from, to)).or_default().push(*flow);
+                }
+            }
+        }
+
+        let hops = (1..).zip(hops).map(|(ttl, flows)| Hop::new(ttl, flows)).collect();
+
+        let links = links.into_iter().map(|((ttl, from, to), flows)| {
+            Link { ttl, from, to, flows }
+        }).collect();
+
+        Self { hops, links }
+    }
+}
+
+impl Hop {
+    fn new(ttl: u8, flows: &Flows) -> Self {
+        let mut interfaces = BTreeMap::<IpAddr, Interface>::new();
+        let mut lost       = 0;
+
+        for (flow, node) in flows {
+            match node {
+                Some(node) => {
+                    let interface = interfaces.entry(node.addr).or_insert(Interface {
+                        addr:  node.addr,
+                        flows: Vec::new(),
+                        rtt:   node.rtt,
+                        last:  node.last(),
+                    });
+                    interface.flows.push(*flow);
+                    interface.rtt  = interface.rtt.min(node.rtt);
+                    interface.last = interface.last || node.last();
+                },
+                None => lost += 1,
+            }
+        }
+
+        Self {
+            ttl:        ttl,
+            interfaces: interfaces.into_values().collect(),
+            probes:     flows.len(),
+            lost:       lost,
+        }
+    }
+}
+
+fn addr(node: Option<&Option<Node>>) -> Option<IpAddr> {
+    Some(node?.as_ref()?.addr)
+}
+
+fn distinct(flows: &Flows) -> usize {
+    let mut addrs = flows.values().filter_map(|node| addr(Some(node))).collect::<Vec<_>>();
+    addrs.sort();
+    addrs.dedup();
+    addrs.len()
+}
+
+fn enough(hop: &Flows, confidence: f64) -> bool {
+    hop.len() >= stop(distinct(hop).max(1), confidence)
+}
+
+fn stop(found: usize, confidence: f64) -> usize {
+    let k = found as f64;
+    let n = ((1.0 - confidence) / (k + 1.0)).ln() / (k / (k + 1.0)).ln();
+    n.ceil().max(1.0) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stop_thresholds() {
+        assert_eq!(6,  stop(1, 0.95));
+        assert_eq!(11, stop(2, 0.95));
+        assert_eq!(16, stop(3, 0.95));
+        assert_eq!(1,  stop(1, 0.0));
+    }
+
+    #[test]
+    fn stop_all_lost() {
+        let mut hop = Flows::new();
+
+        for flow in 0..5 {
+            hop.insert(flow, None);
+            assert!(!enough(&hop, 0.95));
+        }
+
+        hop.insert(5, None);
+        assert!(enough(&hop, 0.95));
+    }
+}
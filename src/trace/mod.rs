@@ -1,11 +1,27 @@
+pub use mda::Graph;
+pub use mda::Hop;
+pub use mda::Interface;
+pub use mda::Link;
+pub use mda::Mda;
+pub use mtr::HopStats;
+pub use mtr::Mtr;
+pub use mtr::Report;
+pub use mtr::Responder;
+pub use pmtu::MtuHop;
+pub use pmtu::PathMtu;
+pub use pmtu::Pmtu;
 pub use probe::Probe;
 pub use probe::Protocol;
+pub use reply::Kind;
 pub use reply::Node;
 pub use trace::Trace;
 pub use trace::Tracer;
 
 mod reply;
 mod icmp;
+mod mda;
+mod mtr;
+mod pmtu;
 mod probe;
 mod sock4;
 mod sock6;
@@ -0,0 +1,150 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use futures::stream::try_unfold;
+use crate::{PingStats, PingSummary};
+use crate::limit::ticks;
+use super::probe::Probe;
+use super::reply::Node;
+use super::trace::Tracer;
+
+#[derive(Debug)]
+pub struct Mtr {
+    pub addr:     IpAddr,
+    pub limit:    u8,
+    pub expiry:   Duration,
+    pub interval: Duration,
+    pub deadline: Option<Duration>,
+    pub window:   usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub round: usize,
+    pub hops:  Vec<HopStats>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HopStats {
+    pub ttl:        u8,
+    pub stats:      PingSummary,
+    pub responders: Vec<Responder>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Responder {
+    pub addr:  IpAddr,
+    pub count: usize,
+    pub first: usize,
+    pub last:  usize,
+}
+
+struct Monitor {
+    round:  usize,
+    limit:  u8,
+    window: usize,
+    hops:   Vec<Track>,
+}
+
+struct Track {
+    stats:      PingStats,
+    responders: Vec<Responder>,
+}
+
+impl Default for Mtr {
+    fn default() -> Self {
+        Self {
+            addr:     IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            limit:    30,
+            expiry:   Duration::from_secs(1),
+            interval: Duration::from_millis(100),
+            deadline: None,
+            window:   0,
+        }
+    }
+}
+
+impl Tracer {
+    pub fn mtr<'a>(&'a self, probe: &'a mut Probe, mtr: &Mtr) -> impl Stream<Item = Result<Report>> + 'a {
+        let Mtr { addr, limit, expiry, interval, deadline, window } = *mtr;
+
+        let ticks   = ticks(interval, deadline).boxed();
+        let monitor = Monitor { round: 0, limit, window, hops: Vec::new() };
+
+        try_unfold((probe, ticks, monitor), move |(probe, mut ticks, mut monitor)| async move {
+            monitor.round += 1;
+
+            for ttl in 1..=monitor.limit {
+                if ticks.next().await.is_none() {
+                    return Ok(None);
+                }
+
+                let node = self.once(probe, ttl, expiry).await?;
+                let done = node.as_ref().map(|node| node.last() || node.addr == addr);
+
+                monitor.record(ttl, node.as_ref());
+
+                if let Some(true) = done {
+                    monitor.limit = ttl;
+                    break;
+                }
+            }
+
+            monitor.hops.truncate(usize::from(monitor.limit));
+
+            let report = monitor.report();
+
+            Ok(Some((report, (probe, ticks, monitor))))
+        })
+    }
+}
+
+impl Monitor {
+    fn record(&mut self, ttl: u8, node: Option<&Node>) {
+        let index  = usize::from(ttl) - 1;
+        let window = self.window;
+
+        if self.hops.len() <= index {
+            self.hops.resize_with(index + 1, || Track {
+                stats:      PingStats::windowed(window),
+                responders: Vec::new(),
+            });
+        }
+
+        let track = &mut self.hops[index];
+        track.stats.update(node.map(|node| node.rtt));
+
+        if let Some(node) = node {
+            let round = self.round;
+            match track.responders.iter_mut().find(|r| r.addr == node.addr) {
+                Some(responder) => {
+                    responder.count += 1;
+                    responder.last   = round;
+                },
+                None => track.responders.push(Responder {
+                    addr:  node.addr,
+                    count: 1,
+                    first: round,
+                    last:  round,
+                }),
+            }
+        }
+    }
+
+    fn report(&self) -> Report {
+        let hops = (1..).zip(&self.hops).map(|(ttl, track)| HopStats {
+            ttl:        ttl,
+            stats:      track.stats.summary(),
+            responders: track.responders.clone(),
+        }).collect();
+
+        Report { round: self.round, hops }
+    }
+}
+
+impl Responder {
+    pub fn fresh(&self, round: usize) -> bool {
+        self.first == round
+    }
+}
@@ -0,0 +1,201 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Error, Result};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use libc::EMSGSIZE;
+use crate::limit::ticks;
+use super::probe::{Probe, Protocol};
+use super::reply::Node;
+use super::trace::Tracer;
+
+#[derive(Debug)]
+pub struct Pmtu {
+    pub proto:    Protocol,
+    pub addr:     IpAddr,
+    pub size:     usize,
+    pub probes:   usize,
+    pub limit:    u8,
+    pub expiry:   Duration,
+    pub interval: Duration,
+    pub deadline: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct PathMtu {
+    pub mtu:  usize,
+    pub hops: Vec<MtuHop>,
+}
+
+#[derive(Debug)]
+pub struct MtuHop {
+    pub ttl:       u8,
+    pub node:      Option<Node>,
+    pub mtu:       usize,
+    pub reported:  Vec<Node>,
+    pub local:     Vec<usize>,
+    pub blackhole: Option<usize>,
+}
+
+enum Attempt {
+    Reply(Node),
+    TooBig(Node),
+    Local,
+    Silent,
+    Expired,
+}
+
+impl Default for Pmtu {
+    fn default() -> Self {
+        Self {
+            proto:    Protocol::default(),
+            addr:     IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            size:     1500,
+            probes:   2,
+            limit:    30,
+            expiry:   Duration::from_secs(1),
+            interval: Duration::from_millis(50),
+            deadline: None,
+        }
+    }
+}
+
+impl Tracer {
+    pub async fn pmtu(&self, pmtu: Pmtu) -> Result<PathMtu> {
+        let Pmtu { proto, addr, size, probes, limit, expiry, interval, deadline } = pmtu;
+
+        if let Protocol::TCP(..) = proto {
+            return Err(anyhow!("PMTU discovery requires ICMP or UDP probes"));
+        }
+
+        let floor = match addr {
+            IpAddr::V4(..) => MIN4,
+            IpAddr::V6(..) => MIN6,
+        };
+
+        let source = self.reserve(proto, addr).await?;
+
+        let mut probe = source.paris(0)?;
+        let mut ticks = ticks(interval, deadline).boxed();
+        let mut mtu   = size.clamp(floor, MAX);
+        let mut hops  = Vec::new();
+
+        probe.dont_fragment();
+
+        'ttl: for ttl in 1..=limit {
+            let mut hop = MtuHop::new(ttl);
+
+            loop {
+                probe.resize(mtu)?;
+
+                let next = match self.attempt(&mut probe, ttl, probes, expiry, &mut ticks).await? {
+                    Attempt::Reply(node) => {
+                        hop.node = Some(node);
+                        break;
+                    },
+                    Attempt::TooBig(node) => {
+                        let size = node.mtu.filter(|&mtu| mtu > 0).and_then(|mtu| usize::try_from(mtu).ok());
+                        hop.reported.push(node);
+                        match size {
+                            Some(size) if size < mtu => size.max(floor),
+                            _                        => plateau(mtu, floor),
+                        }
+                    },
+                    Attempt::Local => {
+                        hop.local.push(mtu);
+                        match self.mtu(addr).await {
+                            Ok(size) if size < mtu => size.max(floor),
+                            _                      => plateau(mtu, floor),
+                        }
+                    },
+                    Attempt::Silent if mtu > floor => {
+                        probe.resize(floor)?;
+                        match self.attempt(&mut probe, ttl, probes, expiry, &mut ticks).await? {
+                            Attempt::Reply(_) => {
+                                hop.blackhole = Some(mtu);
+                                plateau(mtu, floor)
+                            },
+                            Attempt::Expired  => break 'ttl,
+                            _                 => break,
+                        }
+                    },
+                    Attempt::Silent  => break,
+                    Attempt::Expired => break 'ttl,
+                };
+
+                if next >= mtu {
+                    break;
+                }
+
+                mtu = next;
+            }
+
+            hop.mtu = mtu;
+
+            let done = hop.node.as_ref().map(|node| node.last() || node.addr == addr);
+
+            hops.push(hop);
+
+            if let Some(true) = done {
+                break;
+            }
+        }
+
+        Ok(PathMtu { mtu, hops })
+    }
+
+    async fn attempt(
+        &self,
+        probe:  &mut Probe,
+        ttl:    u8,
+        count:  usize,
+        expiry: Duration,
+        ticks:  &mut BoxStream<'static, Instant>,
+    ) -> Result<Attempt> {
+        for _ in 0..count.max(1) {
+            if ticks.next().await.is_none() {
+                return Ok(Attempt::Expired);
+            }
+
+            match self.once(probe, ttl, expiry).await {
+                Ok(Some(node)) if node.mtu.is_some() => return Ok(Attempt::TooBig(node)),
+                Ok(Some(node))                       => return Ok(Attempt::Reply(node)),
+                Ok(None)                             => continue,
+                Err(e) if emsgsize(&e)               => return Ok(Attempt::Local),
+                Err(e)                               => return Err(e),
+            }
+        }
+        Ok(Attempt::Silent)
+    }
+}
+
+impl MtuHop {
+    fn new(ttl: u8) -> Self {
+        Self {
+            ttl:       ttl,
+            node:      None,
+            mtu:       0,
+            reported:  Vec::new(),
+            local:     Vec::new(),
+            blackhole: None,
+        }
+    }
+}
+
+fn plateau(mtu: usize, floor: usize) -> usize {
+    PLATEAUS.iter().copied().find(|&size| size < mtu).unwrap_or(floor).max(floor)
+}
+
+fn emsgsize(e: &Error) -> bool {
+    e.downcast_ref::<io::Error>().and_then(io::Error::raw_os_error) == Some(EMSGSIZE)
+}
+
+const MIN4: usize = 68;
+const MIN6: usize = 1280;
+const MAX:  usize = 65535;
+
+const PLATEAUS: &[usize] = &[
+    MAX, 32000, 17914, 9000, 8166, 4352, 2002, 1500, 1492, 1480, 1280, 1006, 576, 508, 296, 68,
+];
@@ -5,26 +5,32 @@ use anyhow::{Result, anyhow};
 use etherparse::*;
 use crate::icmp::{icmp4, icmp6};
 use super::{Key, Probe};
+use super::probe::fold;
 
 #[derive(Debug)]
 pub struct ICMPv4 {
-    pub src: Ipv4Addr,
-    pub dst: Ipv4Addr,
-    pub id:  u16,
-    pub seq: u16,
+    pub src:  Ipv4Addr,
+    pub dst:  Ipv4Addr,
+    pub id:   u16,
+    pub seq:  u16,
+    pub flow: Option<u16>,
+    pub pad:  usize,
 }
 
 #[derive(Debug)]
 pub struct ICMPv6 {
-    pub src: Ipv6Addr,
-    pub dst: Ipv6Addr,
-    pub id:  u16,
-    pub seq: u16,
+    pub src:  Ipv6Addr,
+    pub dst:  Ipv6Addr,
+    pub id:   u16,
+    pub seq:  u16,
+    pub flow: Option<u16>,
+    pub pad:  usize,
+    pub df:   bool,
 }
 
 impl ICMPv4 {
     pub fn new(src: Ipv4Addr, dst: Ipv4Addr, id: u16, seq: u16) -> Self {
-        Self { src, dst, id, seq }
+        Self { src, dst, id, seq, flow: None, pad: 0 }
     }
 
     pub fn decode(ip: Ipv4Header, tail: &[u8]) -> Result<Probe> {
@@ -38,7 +44,7 @@ impl ICMPv4 {
         let id  = u16::from_be_bytes(tail[4..6].try_into()?);
         let seq = u16::from_be_bytes(tail[6..8].try_into()?);
 
-        Ok(Probe::from(ICMPv4 { src, dst, id, seq }))
+        Ok(Probe::from(ICMPv4::new(src, dst, id, seq)))
     }
 
     pub fn encode<'a>(&self, buf: &'a mut [u8], ttl: u8) -> Result<&'a mut [u8]> {
@@ -46,20 +52,24 @@ impl ICMPv4 {
 
         let src = self.src.octets();
         let dst = self.dst.octets();
-        let len = u16::try_from(icmp4::HEADER_SIZE)?;
+        let len = icmp4::HEADER_SIZE + padding(self.flow);
+        let ip  = u16::try_from(len + self.pad)?;
 
-        let pkt = Ipv4Header::new(len, ttl, IpNumber::Icmp, src, dst);
+        let pkt = Ipv4Header::new(ip, ttl, IpNumber::Icmp, src, dst);
         pkt.write(&mut buf)?;
 
-        let mut pkt = [0u8; icmp4::HEADER_SIZE];
+        let mut pkt = [0u8; icmp4::HEADER_SIZE + PADDING];
         pkt[0..2].copy_from_slice(&[icmp4::ECHO_REQUEST, 0]);
         pkt[2..4].copy_from_slice(&0u16.to_be_bytes());
         pkt[4..6].copy_from_slice(&self.id.to_be_bytes());
         pkt[6..8].copy_from_slice(&self.seq.to_be_bytes());
+        pkt[8..10].copy_from_slice(&compensate(self.seq, self.flow).to_be_bytes());
 
-        let cksum = icmp4::checksum(&pkt).to_be_bytes();
+        let pkt   = &mut pkt[..len];
+        let cksum = icmp4::checksum(pkt).to_be_bytes();
         pkt[2..4].copy_from_slice(&cksum);
-        buf.write_all(&pkt)?;
+        buf.write_all(pkt)?;
+        buf.write_all(&vec![0; self.pad])?;
 
         let n = usize::try_from(buf.position())?;
 
@@ -72,14 +82,23 @@ impl ICMPv4 {
         Key::ICMP(src, dst, self.id)
     }
 
+    pub fn size(&self) -> usize {
+        Ipv4Header::SERIALIZED_SIZE + icmp4::HEADER_SIZE + padding(self.flow) + self.pad
+    }
+
+    pub fn paris(&mut self, flow: u16) -> Result<()> {
+        self.flow = Some(flow);
+        Ok(())
+    }
+
     pub fn increment(&mut self) {
-        self.seq += 1;
+        self.seq = self.seq.wrapping_add(1);
     }
 }
 
 impl ICMPv6 {
     pub fn new(src: Ipv6Addr, dst: Ipv6Addr, id: u16, seq: u16) -> Self {
-        Self { src, dst, id, seq }
+        Self { src, dst, id, seq, flow: None, pad: 0, df: false }
     }
 
     pub fn decode(ip: Ipv6Header, tail: &[u8]) -> Result<Probe> {
@@ -93,18 +112,22 @@ impl ICMPv6 {
         let id  = u16::from_be_bytes(tail[4..6].try_into()?);
         let seq = u16::from_be_bytes(tail[6..8].try_into()?);
 
-        Ok(Probe::from(ICMPv6 { src, dst, id, seq }))
+        Ok(Probe::from(ICMPv6::new(src, dst, id, seq)))
     }
 
     pub fn encode<'a>(&self, buf: &'a mut [u8]) -> Result<&'a mut [u8]> {
         let mut buf = Cursor::new(buf);
 
-        let mut pkt = [0u8; icmp6::HEADER_SIZE];
+        let len = icmp6::HEADER_SIZE + padding(self.flow);
+
+        let mut pkt = [0u8; icmp6::HEADER_SIZE + PADDING];
         pkt[0..2].copy_from_slice(&[icmp6::ECHO_REQUEST, 0]);
         pkt[2..4].copy_from_slice(&0u16.to_be_bytes());
         pkt[4..6].copy_from_slice(&self.id.to_be_bytes());
         pkt[6..8].copy_from_slice(&self.seq.to_be_bytes());
-        buf.write_all(&pkt)?;
+        pkt[8..10].copy_from_slice(&compensate(self.seq, self.flow).to_be_bytes());
+        buf.write_all(&pkt[..len])?;
+        buf.write_all(&vec![0; self.pad])?;
 
         let n = usize::try_from(buf.position())?;
 
@@ -117,7 +140,97 @@ impl ICMPv6 {
         Key::ICMP(src, dst, self.id)
     }
 
+    pub fn size(&self) -> usize {
+        Ipv6Header::SERIALIZED_SIZE + icmp6::HEADER_SIZE + padding(self.flow) + self.pad
+    }
+
+    pub fn paris(&mut self, flow: u16) -> Result<()> {
+        self.flow = Some(flow);
+        Ok(())
+    }
+
     pub fn increment(&mut self) {
-        self.seq += 1;
+        self.seq = self.seq.wrapping_add(1);
     }
 }
+
+fn padding(flow: Option<u16>) -> usize {
+    match flow {
+        Some(_) => PADDING,
+        None    => 0,
+    }
+}
+
+fn compensate(seq: u16, flow: Option<u16>) -> u16 {
+    fold(u32::from(!seq) + u32::from(flow.unwrap_or(0)))
+}
+
+const PADDING: usize = 2;
+
+#[cfg(test)]
+mod test {
+    use super::super::Ident;
+    use super::*;
+
+    #[test]
+    fn compensate_icmp4() {
+        let mut sums = Vec::new();
+
+        for &seq in SEQS {
+            let mut probe = ICMPv4::new(SRC4, DST4, ID, seq);
+            probe.paris(FLOW).unwrap();
+
+            let mut buf = [0u8; 64];
+            let pkt = probe.encode(&mut buf, 64).unwrap();
+            let (_, tail) = Ipv4Header::from_slice(pkt).unwrap();
+
+            assert_eq!(0, icmp4::checksum(tail));
+            sums.push(u16::from_be_bytes([tail[2], tail[3]]));
+
+            let probe = Probe::decode4(pkt).unwrap();
+            assert_eq!(Ident::ICMP(seq), probe.ident());
+        }
+
+        assert!(sums.iter().all(|&sum| sum == sums[0]));
+    }
+
+    #[test]
+    fn compensate_icmp6() {
+        let mut sums = Vec::new();
+
+        for &seq in SEQS {
+            let mut probe = ICMPv6::new(SRC6, DST6, ID, seq);
+            probe.paris(FLOW).unwrap();
+
+            let mut buf = [0u8; 64];
+            let pkt = probe.encode(&mut buf).unwrap();
+
+            sums.push(icmp4::checksum(pkt));
+
+            let probe = ICMPv6::decode(ipv6(pkt.len()), pkt).unwrap();
+            assert_eq!(Ident::ICMP(seq), probe.ident());
+        }
+
+        assert!(sums.iter().all(|&sum| sum == sums[0]));
+    }
+
+    fn ipv6(len: usize) -> Ipv6Header {
+        Ipv6Header {
+            traffic_class:  0,
+            flow_label:     0,
+            payload_length: len as u16,
+            next_header:    IpNumber::IPv6Icmp as u8,
+            hop_limit:      64,
+            source:         SRC6.octets(),
+            destination:    DST6.octets(),
+        }
+    }
+
+    const SRC4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+    const DST4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SRC6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+    const DST6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    const ID:   u16      = 0x1234;
+    const FLOW: u16      = 7;
+    const SEQS: &[u16]   = &[0, 1, 2, 1000, u16::MAX];
+}
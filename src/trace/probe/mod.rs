@@ -1,3 +1,4 @@
+pub use probe::Ident;
 pub use probe::Key;
 pub use probe::Probe;
 pub use probe::Probes;
@@ -47,6 +47,14 @@ pub struct Probes {
 pub const PORT_MIN: u16 = 33434;
 pub const PORT_MAX: u16 = 65407;
 
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Ident {
+    ICMP(u16),
+    TCP(u32),
+    Port(u16),
+    Sum(u16),
+}
+
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub enum Key {
     ICMP(IpAddr, IpAddr, u16),
@@ -55,22 +63,22 @@ pub enum Key {
 }
 
 impl Probe {
-    pub fn decode4(pkt: &[u8]) -> Result<Key> {
+    pub fn decode4(pkt: &[u8]) -> Result<Probe> {
         let (head, tail) = Ipv4Header::from_slice(pkt)?;
         match head.protocol {
-            ICMP4 => Ok(ICMPv4::decode(head, tail)?.key()),
-            TCP   => Ok(TCPv4::decode(head, tail)?.key()),
-            UDP   => Ok(UDPv4::decode(head, tail)?.key()),
+            ICMP4 => ICMPv4::decode(head, tail),
+            TCP   => TCPv4::decode(head, tail),
+            UDP   => UDPv4::decode(head, tail),
             other => Err(anyhow!("unsupported protocol: {}", other)),
         }
     }
 
-    pub fn decode6(pkt: &[u8]) -> Result<Key> {
+    pub fn decode6(pkt: &[u8]) -> Result<Probe> {
         let (head, tail) = Ipv6Header::from_slice(pkt)?;
         match head.next_header {
-            ICMP6 => Ok(ICMPv6::decode(head, tail)?.key()),
-            TCP   => Ok(TCPv6::decode(head, tail)?.key()),
-            UDP   => Ok(UDPv6::decode(head, tail)?.key()),
+            ICMP6 => ICMPv6::decode(head, tail),
+            TCP   => TCPv6::decode(head, tail),
+            UDP   => UDPv6::decode(head, tail),
             other => Err(anyhow!("unsupported protocol: {}", other)),
         }
     }
@@ -108,6 +116,81 @@ impl Probe {
         }
     }
 
+    pub fn ident(&self) -> Ident {
+        match self {
+            Self::ICMP(ICMP::V4(v4)) => Ident::ICMP(v4.seq),
+            Self::ICMP(ICMP::V6(v6)) => Ident::ICMP(v6.seq),
+            Self::TCP(TCP::V4(v4))   => Ident::TCP(v4.seq),
+            Self::TCP(TCP::V6(v6))   => Ident::TCP(v6.seq),
+            Self::UDP(UDP::V4(v4))   => v4.ident(),
+            Self::UDP(UDP::V6(v6))   => v6.ident(),
+        }
+    }
+
+    pub fn idents(&self) -> Vec<Ident> {
+        match self {
+            Self::UDP(UDP::V4(v4)) => vec![Ident::Port(v4.dst.port()), Ident::Sum(v4.seq)],
+            Self::UDP(UDP::V6(v6)) => vec![Ident::Port(v6.dst.port()), Ident::Sum(v6.seq)],
+            _                      => vec![self.ident()],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::ICMP(ICMP::V4(v4)) => v4.size(),
+            Self::ICMP(ICMP::V6(v6)) => v6.size(),
+            Self::TCP(TCP::V4(v4))   => v4.size(),
+            Self::TCP(TCP::V6(v6))   => v6.size(),
+            Self::UDP(UDP::V4(v4))   => v4.size(),
+            Self::UDP(UDP::V6(v6))   => v6.size(),
+        }
+    }
+
+    pub fn resize(&mut self, size: usize) -> Result<()> {
+        let min = self.size() - *self.pad()?;
+        *self.pad()? = size.checked_sub(min).ok_or_else(|| {
+            anyhow!("probe size below minimum of {}", min)
+        })?;
+        Ok(())
+    }
+
+    pub fn df(&self) -> bool {
+        match self {
+            Self::ICMP(ICMP::V6(v6)) => v6.df,
+            Self::UDP(UDP::V6(v6))   => v6.df,
+            _                        => false,
+        }
+    }
+
+    pub fn dont_fragment(&mut self) {
+        match self {
+            Self::ICMP(ICMP::V6(v6)) => v6.df = true,
+            Self::UDP(UDP::V6(v6))   => v6.df = true,
+            _                        => (),
+        }
+    }
+
+    pub fn paris(&mut self, flow: u16) -> Result<()> {
+        match self {
+            Self::ICMP(ICMP::V4(v4)) => v4.paris(flow),
+            Self::ICMP(ICMP::V6(v6)) => v6.paris(flow),
+            Self::TCP(_)             => Ok(()),
+            Self::UDP(UDP::V4(v4))   => v4.paris(flow),
+            Self::UDP(UDP::V6(v6))   => v6.paris(flow),
+        }
+    }
+
+    pub fn sequence(&mut self, seq: u16) {
+        match self {
+            Self::ICMP(ICMP::V4(v4)) => v4.seq = seq,
+            Self::ICMP(ICMP::V6(v6)) => v6.seq = seq,
+            Self::TCP(TCP::V4(v4))   => v4.seq = u32::from(seq),
+            Self::TCP(TCP::V6(v6))   => v6.seq = u32::from(seq),
+            Self::UDP(UDP::V4(v4))   => v4.seq = seq,
+            Self::UDP(UDP::V6(v6))   => v6.seq = seq,
+        }
+    }
+
     pub fn increment(&mut self) {
         match self {
             Self::ICMP(ICMP::V4(v4)) => v4.increment(),
@@ -118,6 +201,16 @@ impl Probe {
             Self::UDP(UDP::V6(v6))   => v6.increment(),
         }
     }
+
+    fn pad(&mut self) -> Result<&mut usize> {
+        match self {
+            Self::ICMP(ICMP::V4(v4)) => Ok(&mut v4.pad),
+            Self::ICMP(ICMP::V6(v6)) => Ok(&mut v6.pad),
+            Self::TCP(_)             => Err(anyhow!("TCP probes cannot be resized")),
+            Self::UDP(UDP::V4(v4))   => Ok(&mut v4.pad),
+            Self::UDP(UDP::V6(v6))   => Ok(&mut v6.pad),
+        }
+    }
 }
 
 impl Probes {
@@ -147,6 +240,12 @@ impl Probes {
             Protocol::UDP(port) => Probe::UDP(UDP::try_from((src, dst, port))?),
         })
     }
+
+    pub fn paris(&self, flow: u16) -> Result<Probe> {
+        let mut probe = self.probe()?;
+        probe.paris(flow)?;
+        Ok(probe)
+    }
 }
 
 impl Default for Protocol {
@@ -235,6 +334,13 @@ fn sa6(addr: Ipv6Addr, port: u16) -> SocketAddrV6 {
     SocketAddrV6::new(addr, port, 0, 0)
 }
 
+pub fn fold(mut sum: u32) -> u16 {
+    while (sum >> 16) > 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
 fn invalid() -> Error {
     anyhow!("mixed IPv4 and IPv6 addresses")
 }
@@ -56,8 +56,12 @@ impl TCPv4 {
         Ok(&mut buf.into_inner()[..n])
     }
 
+    pub fn size(&self) -> usize {
+        Ipv4Header::SERIALIZED_SIZE + TCP_MINIMUM_HEADER_SIZE
+    }
+
     pub fn increment(&mut self) {
-        self.seq += 1;
+        self.seq = self.seq.wrapping_add(1);
     }
 }
 
@@ -98,7 +102,11 @@ impl TCPv6 {
         Ok(&mut buf.into_inner()[..n])
     }
 
+    pub fn size(&self) -> usize {
+        Ipv6Header::SERIALIZED_SIZE + TCP_MINIMUM_HEADER_SIZE
+    }
+
     pub fn increment(&mut self) {
-        self.seq += 1;
+        self.seq = self.seq.wrapping_add(1);
     }
 }
@@ -1,24 +1,34 @@
-use std::io::Cursor;
+use std::convert::TryFrom;
+use std::io::{Cursor, Write};
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use etherparse::*;
-use super::Probe;
+use crate::icmp::icmp4;
+use super::{Ident, Probe, PORT_MAX, PORT_MIN};
+use super::probe::fold;
 
 #[derive(Debug)]
 pub struct UDPv4 {
-    pub src: SocketAddrV4,
-    pub dst: SocketAddrV4,
+    pub src:  SocketAddrV4,
+    pub dst:  SocketAddrV4,
+    pub seq:  u16,
+    pub flow: Option<u16>,
+    pub pad:  usize,
 }
 
 #[derive(Debug)]
 pub struct UDPv6 {
-    pub src: SocketAddrV6,
-    pub dst: SocketAddrV6,
+    pub src:  SocketAddrV6,
+    pub dst:  SocketAddrV6,
+    pub seq:  u16,
+    pub flow: Option<u16>,
+    pub pad:  usize,
+    pub df:   bool,
 }
 
 impl UDPv4 {
     pub fn new(src: SocketAddrV4, dst: SocketAddrV4) -> Self {
-        Self { src, dst }
+        Self { src, dst, seq: 1, flow: None, pad: 0 }
     }
 
     pub fn decode(ip: Ipv4Header, tail: &[u8]) -> Result<Probe> {
@@ -28,8 +38,9 @@ impl UDPv4 {
         let pkt = UdpHeaderSlice::from_slice(tail)?;
         let src = SocketAddrV4::new(src, pkt.source_port());
         let dst = SocketAddrV4::new(dst, pkt.destination_port());
+        let seq = pkt.checksum();
 
-        Ok(Probe::from(UDPv4 { src, dst }))
+        Ok(Probe::from(UDPv4 { seq, ..UDPv4::new(src, dst) }))
     }
 
     pub fn encode<'a>(&self, buf: &'a mut [u8], ttl: u8) -> Result<&'a mut [u8]> {
@@ -41,20 +52,51 @@ impl UDPv4 {
         let pkt = PacketBuilder::ipv4(src, dst, ttl);
         let pkt = pkt.udp(self.src.port(), self.dst.port());
 
-        let n = pkt.size(0);
-        pkt.write(&mut buf, &[])?;
+        let payload = [payload(self.flow), &vec![0; self.pad]].concat();
+        let n = pkt.size(payload.len());
+        pkt.write(&mut buf, &payload)?;
 
-        Ok(&mut buf.into_inner()[..n])
+        let pkt = &mut buf.into_inner()[..n];
+
+        if self.flow.is_some() {
+            let head = Ipv4Header::SERIALIZED_SIZE;
+            let sum  = u16::from_be_bytes([pkt[head + 6], pkt[head + 7]]);
+            let (cksum, data) = compensate(self.seq, sum);
+            pkt[head + 6..head + 8].copy_from_slice(&cksum.to_be_bytes());
+            pkt[head + 8..head + 10].copy_from_slice(&data.to_be_bytes());
+        }
+
+        Ok(pkt)
+    }
+
+    pub fn size(&self) -> usize {
+        Ipv4Header::SERIALIZED_SIZE + UdpHeader::SERIALIZED_SIZE + payload(self.flow).len() + self.pad
+    }
+
+    pub fn ident(&self) -> Ident {
+        match self.flow {
+            Some(_) => Ident::Sum(self.seq.max(1)),
+            None    => Ident::Port(self.dst.port()),
+        }
+    }
+
+    pub fn paris(&mut self, flow: u16) -> Result<()> {
+        self.dst.set_port(port(self.dst.port(), flow)?);
+        self.flow = Some(flow);
+        Ok(())
     }
 
     pub fn increment(&mut self) {
-        self.dst.set_port(self.dst.port() + 1);
+        match self.flow {
+            Some(_) => self.seq = self.seq.wrapping_add(1),
+            None    => self.dst.set_port(cycle(self.dst.port())),
+        }
     }
 }
 
 impl UDPv6 {
     pub fn new(src: SocketAddrV6, dst: SocketAddrV6) -> Self {
-        Self { src, dst }
+        Self { src, dst, seq: 1, flow: None, pad: 0, df: false }
     }
 
     pub fn decode(ip: Ipv6Header, tail: &[u8]) -> Result<Probe> {
@@ -64,8 +106,9 @@ impl UDPv6 {
         let pkt = UdpHeaderSlice::from_slice(tail)?;
         let src = SocketAddrV6::new(src, pkt.source_port(), 0, 0);
         let dst = SocketAddrV6::new(dst, pkt.destination_port(), 0, 0);
+        let seq = pkt.checksum();
 
-        Ok(Probe::from(UDPv6 { src, dst }))
+        Ok(Probe::from(UDPv6 { seq, ..UDPv6::new(src, dst) }))
     }
 
     pub fn encode<'a>(&self, buf: &'a mut [u8]) -> Result<&'a mut [u8]> {
@@ -73,15 +116,162 @@ impl UDPv6 {
 
         let src = self.src.port();
         let dst = self.dst.port();
-        let pkt = UdpHeader::without_ipv4_checksum(src, dst, 0)?;
+        let len = payload(self.flow).len();
+        let pkt = UdpHeader::without_ipv4_checksum(src, dst, len + self.pad)?;
 
         pkt.write(&mut buf)?;
         let n = buf.position() as usize;
 
-        Ok(&mut buf.into_inner()[..n])
+        buf.write_all(payload(self.flow))?;
+        buf.write_all(&vec![0; self.pad])?;
+
+        let pkt = buf.into_inner();
+
+        if self.flow.is_some() {
+            let sum = self.pseudo(&pkt[..n])?;
+            let (_, data) = compensate(self.seq, sum);
+            pkt[n..n + 2].copy_from_slice(&data.to_be_bytes());
+        }
+
+        Ok(&mut pkt[..n + len + self.pad])
+    }
+
+    pub fn size(&self) -> usize {
+        Ipv6Header::SERIALIZED_SIZE + UdpHeader::SERIALIZED_SIZE + payload(self.flow).len() + self.pad
+    }
+
+    pub fn ident(&self) -> Ident {
+        match self.flow {
+            Some(_) => Ident::Sum(self.seq.max(1)),
+            None    => Ident::Port(self.dst.port()),
+        }
+    }
+
+    pub fn paris(&mut self, flow: u16) -> Result<()> {
+        self.dst.set_port(port(self.dst.port(), flow)?);
+        self.flow = Some(flow);
+        Ok(())
     }
 
     pub fn increment(&mut self) {
-        self.dst.set_port(self.dst.port() + 1);
+        match self.flow {
+            Some(_) => self.seq = self.seq.wrapping_add(1),
+            None    => self.dst.set_port(cycle(self.dst.port())),
+        }
+    }
+
+    fn pseudo(&self, head: &[u8]) -> Result<u16> {
+        let len = u32::try_from(head.len() + PADDING + self.pad)?;
+
+        let mut pkt = Vec::with_capacity(48);
+        pkt.extend_from_slice(&self.src.ip().octets());
+        pkt.extend_from_slice(&self.dst.ip().octets());
+        pkt.extend_from_slice(&len.to_be_bytes());
+        pkt.extend_from_slice(&u32::from(UDP).to_be_bytes());
+        pkt.extend_from_slice(head);
+
+        Ok(icmp4::checksum(&pkt))
+    }
+}
+
+fn port(port: u16, flow: u16) -> Result<u16> {
+    port.checked_add(flow).ok_or_else(|| anyhow!("flow {} exceeds UDP port range from {}", flow, port))
+}
+
+fn cycle(port: u16) -> u16 {
+    match port {
+        PORT_MAX..=u16::MAX => PORT_MIN,
+        port                => port + 1,
     }
 }
+
+fn payload(flow: Option<u16>) -> &'static [u8] {
+    match flow {
+        Some(_) => &[0; PADDING],
+        None    => &[],
+    }
+}
+
+fn compensate(seq: u16, sum: u16) -> (u16, u16) {
+    let cksum = seq.max(1);
+    (cksum, fold(u32::from(!cksum) + u32::from(sum)))
+}
+
+const PADDING: usize = 2;
+const UDP:     u8    = IpNumber::Udp as u8;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compensate_udp4() {
+        let mut heads = Vec::new();
+
+        for &seq in SEQS {
+            let mut probe = UDPv4::new(SRC4, DST4);
+            probe.paris(FLOW).unwrap();
+            probe.seq = seq;
+
+            let mut buf = [0u8; 64];
+            let pkt = probe.encode(&mut buf, 64).unwrap();
+            let (ip, tail) = Ipv4Header::from_slice(pkt).unwrap();
+            let (udp, data) = UdpHeader::from_slice(tail).unwrap();
+
+            assert_eq!(seq.max(1), udp.checksum);
+            assert_eq!(udp.checksum, udp.calc_checksum_ipv4(&ip, data).unwrap());
+            heads.push((ip.header_checksum, udp.source_port, udp.destination_port));
+
+            let probe = Probe::decode4(pkt).unwrap();
+            assert!(probe.idents().contains(&Ident::Sum(seq.max(1))));
+        }
+
+        assert!(heads.iter().all(|&head| head == heads[0]));
+    }
+
+    #[test]
+    fn compensate_udp6() {
+        let mut heads = Vec::new();
+
+        for &seq in SEQS {
+            let mut probe = UDPv6::new(SRC6, DST6);
+            probe.paris(FLOW).unwrap();
+            probe.seq = seq;
+
+            let mut buf = [0u8; 64];
+            let pkt = probe.encode(&mut buf).unwrap();
+            let (mut udp, data) = UdpHeader::from_slice(pkt).unwrap();
+
+            udp.checksum = udp.calc_checksum_ipv6_raw(SRC6.ip().octets(), DST6.ip().octets(), data).unwrap();
+            assert_eq!(seq.max(1), udp.checksum);
+            heads.push((udp.source_port, udp.destination_port));
+
+            let mut pkt = pkt.to_vec();
+            pkt[6..8].copy_from_slice(&udp.checksum.to_be_bytes());
+
+            let probe = UDPv6::decode(ipv6(pkt.len()), &pkt).unwrap();
+            assert!(probe.idents().contains(&Ident::Sum(seq.max(1))));
+        }
+
+        assert!(heads.iter().all(|&head| head == heads[0]));
+    }
+
+    fn ipv6(len: usize) -> Ipv6Header {
+        Ipv6Header {
+            traffic_class:  0,
+            flow_label:     0,
+            payload_length: len as u16,
+            next_header:    UDP,
+            hop_limit:      64,
+            source:         SRC6.ip().octets(),
+            destination:    DST6.ip().octets(),
+        }
+    }
+
+    const SRC4: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 2), 40000);
+    const DST4: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), PORT_MIN);
+    const SRC6: SocketAddrV6 = SocketAddrV6::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2), 40000, 0, 0);
+    const DST6: SocketAddrV6 = SocketAddrV6::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), PORT_MIN, 0, 0);
+    const FLOW: u16          = 7;
+    const SEQS: &[u16]       = &[1, 2, 1000, u16::MAX];
+}
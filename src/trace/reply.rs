@@ -1,11 +1,106 @@
 use std::net::IpAddr;
 use std::time::{Duration, Instant};
+use crate::Clock;
+use crate::icmp::{icmp4, icmp6, Extensions};
 
-#[derive(Debug)]
-pub enum Node {
-    Node(u8, IpAddr, Duration, bool),
-    None(u8)
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub ttl:        u8,
+    pub addr:       IpAddr,
+    pub name:       Option<String>,
+    pub asn:        Option<u32>,
+    pub rtt:        Duration,
+    pub kind:       Kind,
+    pub reply_ttl:  Option<u8>,
+    pub quoted_ttl: Option<u8>,
+    pub quoted_tos: Option<u8>,
+    pub size:       usize,
+    pub mtu:        Option<u32>,
+    pub ext:        Extensions,
+    pub clock:      Clock,
+    pub late:       bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    ICMPv4(u8, u8),
+    ICMPv6(u8, u8),
+    TCP,
 }
 
 #[derive(Clone, Debug)]
-pub struct Echo(pub IpAddr, pub Instant, pub bool);
+pub struct Echo {
+    pub addr:   IpAddr,
+    pub when:   Instant,
+    pub kind:   Kind,
+    pub ttl:    Option<u8>,
+    pub quoted: Option<(u8, u8)>,
+    pub size:   usize,
+    pub mtu:    Option<u32>,
+    pub ext:    Extensions,
+    pub clock:  Clock,
+}
+
+impl Node {
+    pub fn new(ttl: u8, echo: Echo, sent: Instant) -> Self {
+        let Echo { addr, when, kind, ttl: reply_ttl, quoted, size, mtu, ext, clock } = echo;
+        Self {
+            ttl:        ttl,
+            addr:       addr,
+            name:       None,
+            asn:        None,
+            rtt:        when.saturating_duration_since(sent),
+            kind:       kind,
+            reply_ttl:  reply_ttl,
+            quoted_ttl: quoted.map(|(ttl, _)| ttl),
+            quoted_tos: quoted.map(|(_, tos)| tos),
+            size:       size,
+            mtu:        mtu,
+            ext:        ext,
+            clock:      clock,
+            late:       false,
+        }
+    }
+
+    pub fn late(ttl: u8, echo: Echo, sent: Instant) -> Self {
+        Self { late: true, ..Self::new(ttl, echo, sent) }
+    }
+
+    pub fn last(&self) -> bool {
+        self.kind.last()
+    }
+
+    pub fn annotation(&self) -> Option<&'static str> {
+        self.kind.annotation()
+    }
+}
+
+impl Kind {
+    pub fn last(&self) -> bool {
+        !matches!(self,
+            Kind::ICMPv4(icmp4::TIME_EXCEEDED, _) | Kind::ICMPv4(icmp4::UNREACHABLE, 4) |
+            Kind::ICMPv6(icmp6::TIME_EXCEEDED, _) | Kind::ICMPv6(icmp6::TOO_BIG,     _)
+        )
+    }
+
+    pub fn annotation(&self) -> Option<&'static str> {
+        match *self {
+            Kind::ICMPv4(icmp4::UNREACHABLE, code) => match code {
+                0 | 6 | 11  => Some("!N"),
+                1 | 7 | 12  => Some("!H"),
+                2           => Some("!P"),
+                4           => Some("!F"),
+                9 | 10 | 13 => Some("!X"),
+                _           => None,
+            },
+            Kind::ICMPv6(icmp6::UNREACHABLE, code) => match code {
+                0           => Some("!N"),
+                3           => Some("!H"),
+                1 | 5 | 6   => Some("!X"),
+                _           => None,
+            },
+            Kind::ICMPv6(icmp6::TOO_BIG, _)        => Some("!F"),
+            _                                      => None,
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::convert::TryFrom;
+use std::io::IoSliceMut;
 use std::net::{IpAddr, SocketAddr};
 use std::time::Instant;
 use std::sync::Arc;
@@ -9,8 +10,10 @@ use log::{debug, error};
 use raw_socket::tokio::prelude::*;
 use tokio::sync::Mutex;
 use crate::{Bind, RouteSocket};
-use super::probe::{Key, Probe};
-use super::reply::Echo;
+use crate::clock::{self, stamp};
+use crate::icmp::Extensions;
+use super::probe::{Ident, Key, Probe};
+use super::reply::{Echo, Kind};
 use super::state::State;
 
 pub struct Sock4 {
@@ -32,6 +35,7 @@ impl Sock4 {
 
         tcp.bind(bind.sa4()).await?;
         udp.bind(bind.sa4()).await?;
+        clock::enable(&tcp)?;
 
         let enable: c_int = 6;
         tcp.set_sockopt(Level::IPV4, Name::IPV4_HDRINCL, &enable)?;
@@ -55,7 +59,7 @@ impl Sock4 {
     }
 
     pub async fn send(&self, probe: &Probe, ttl: u8) -> Result<Instant> {
-        let mut pkt = [0u8; 64];
+        let mut pkt = vec![0u8; probe.size()];
 
         let pkt = probe.encode(&mut pkt, ttl)?;
         let dst = probe.dst();
@@ -66,40 +70,60 @@ impl Sock4 {
             pkt[6..8].copy_from_slice(&0u16.to_ne_bytes());
         }
 
-        match probe {
+        let sock = match probe {
             Probe::ICMP(..) => self.icmp.lock().await,
             Probe::TCP(..)  => self.tcp.lock().await,
             Probe::UDP(..)  => self.udp.lock().await,
-        }.send_to(pkt, &dst).await?;
+        };
 
-        Ok(Instant::now())
+        let sent = Instant::now();
+        sock.send_to(pkt, &dst).await?;
+
+        Ok(sent)
     }
 
     pub async fn source(&self, dst: IpAddr) -> Result<IpAddr> {
         let mut route = self.route.lock().await;
         route.source(SocketAddr::new(dst, 1234)).await
     }
+
+    pub async fn mtu(&self, dst: IpAddr) -> Result<usize> {
+        let mut route = self.route.lock().await;
+        route.mtu(SocketAddr::new(dst, 1234)).await
+    }
 }
 
 async fn recv(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
     let mut pkt = [0u8; 128];
+    let mut ctl = [0u8; 128];
+
     loop {
-        let (n, from) = sock.recv_from(&mut pkt).await?;
+        let iovec = &[IoSliceMut::new(&mut pkt)];
+        let (n, from) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
 
-        let now = Instant::now();
+        let (now, clock) = stamp(&ctl);
         let pkt = Ipv4Header::from_slice(&pkt[..n])?;
 
         if let (ip @ Ipv4Header { protocol: TCP, .. }, tail) = pkt {
             let src = IpAddr::V4(ip.source.into());
             let dst = IpAddr::V4(ip.destination.into());
 
-            let pkt = TcpHeaderSlice::from_slice(tail)?;
-            let dst = SocketAddr::new(dst, pkt.destination_port());
-            let key = Key::TCP(dst, src);
-
-            if let Some(tx) = state.sender(&key) {
-                let _ = tx.send(Echo(from.ip(), now, true));
-            }
+            let pkt   = TcpHeaderSlice::from_slice(tail)?;
+            let dst   = SocketAddr::new(dst, pkt.destination_port());
+            let key   = Key::TCP(dst, src);
+            let ident = Ident::TCP(pkt.acknowledgment_number().wrapping_sub(1));
+
+            state.deliver(&key, &[ident], Echo {
+                addr:   from.ip(),
+                when:   now,
+                kind:   Kind::TCP,
+                ttl:    Some(ip.time_to_live),
+                quoted: None,
+                size:   tail.len(),
+                mtu:    None,
+                ext:    Extensions::default(),
+                clock:  clock,
+            });
         }
     }
 }
@@ -4,13 +4,17 @@ use std::time::Instant;
 use std::sync::Arc;
 use anyhow::Result;
 use etherparse::TcpHeader;
-use libc::c_int;
+use libc::{IPPROTO_IPV6, IPV6_DONTFRAG, c_int};
 use log::{debug, error};
+use raw_socket::control::Raw;
 use raw_socket::tokio::prelude::*;
 use tokio::sync::Mutex;
 use crate::{Bind, RouteSocket};
-use super::probe::{Key, Probe};
-use super::reply::Echo;
+use crate::clock::{self, stamp};
+use crate::icmp::Extensions;
+use crate::recv::pktinfo;
+use super::probe::{Ident, Key, Probe};
+use super::reply::{Echo, Kind};
 use super::state::State;
 
 pub struct Sock6 {
@@ -34,6 +38,8 @@ impl Sock6 {
         let enable: c_int = 1;
         tcp.set_sockopt(Level::IPV6, Name::IPV6_CHECKSUM, &offset)?;
         tcp.set_sockopt(Level::IPV6, Name::IPV6_RECVPKTINFO, &enable)?;
+        tcp.set_sockopt(Level::IPV6, Name::IPV6_RECVHOPLIMIT, &enable)?;
+        clock::enable(&tcp)?;
 
         let offset: c_int = 6;
         udp.set_sockopt(Level::IPV6, Name::IPV6_CHECKSUM, &offset)?;
@@ -59,55 +65,70 @@ impl Sock6 {
     pub async fn send(&self, probe: &Probe, ttl: u8) -> Result<Instant> {
         let mut dst = probe.dst();
         let mut ctl = [0u8; 64];
-        let mut pkt = [0u8; 64];
+        let mut pkt = vec![0u8; probe.size()];
 
         let pkt = probe.encode(&mut pkt, ttl)?;
         dst.set_port(0);
 
-        let hops = CMsg::Ipv6HopLimit(ttl as c_int);
-        let ctl  = CMsg::encode(&mut ctl, &[hops])?;
+        let dfrag = c_int::from(probe.df()).to_ne_bytes();
+        let ctl   = CMsg::encode(&mut ctl, &[
+            CMsg::Ipv6HopLimit(ttl as c_int),
+            Raw::from(IPPROTO_IPV6, IPV6_DONTFRAG, &dfrag).into(),
+        ])?;
         let data = &[IoSlice::new(pkt)];
 
-        match probe {
+        let sock = match probe {
             Probe::ICMP(..) => self.icmp.lock().await,
             Probe::TCP(..)  => self.tcp.lock().await,
             Probe::UDP(..)  => self.udp.lock().await,
-        }.send_msg(&dst, data, Some(ctl)).await?;
+        };
 
-        Ok(Instant::now())
+        let sent = Instant::now();
+        sock.send_msg(&dst, data, Some(ctl)).await?;
+
+        Ok(sent)
     }
 
     pub async fn source(&self, dst: IpAddr) -> Result<IpAddr> {
         let mut route = self.route.lock().await;
         route.source(SocketAddr::new(dst, 1234)).await
     }
+
+    pub async fn mtu(&self, dst: IpAddr) -> Result<usize> {
+        let mut route = self.route.lock().await;
+        route.mtu(SocketAddr::new(dst, 1234)).await
+    }
 }
 
 async fn recv(sock: Arc<RawSocket>, state: Arc<State>) -> Result<()> {
     let mut pkt = [0u8; 64];
-    let mut ctl = [0u8; 64];
+    let mut ctl = [0u8; 128];
 
     loop {
         let iovec = &[IoSliceMut::new(&mut pkt)];
         let (n, src) = sock.recv_msg(iovec, Some(&mut ctl)).await?;
 
-        let now = Instant::now();
+        let (now, clock) = stamp(&ctl);
+        let (dst, hops) = pktinfo(&ctl);
         let pkt = TcpHeader::from_slice(&pkt[..n]);
-        let dst = CMsg::decode(&ctl).find_map(|msg| {
-            match msg {
-                CMsg::Ipv6PktInfo(info) => Some(info.addr().into()),
-                _                       => None,
-            }
-        });
 
         if let (Ok((head, _tail)), Some(dst)) = (pkt, dst) {
-            let src = src.ip();
-            let dst = SocketAddr::new(dst, head.destination_port);
-            let key = Key::TCP(dst, src);
-
-            if let Some(tx) = state.sender(&key) {
-                let _ = tx.send(Echo(src, now, true));
-            }
+            let src   = src.ip();
+            let dst   = SocketAddr::new(dst, head.destination_port);
+            let key   = Key::TCP(dst, src);
+            let ident = Ident::TCP(head.acknowledgment_number.wrapping_sub(1));
+
+            state.deliver(&key, &[ident], Echo {
+                addr:   src,
+                when:   now,
+                kind:   Kind::TCP,
+                ttl:    hops,
+                quoted: None,
+                size:   n,
+                mtu:    None,
+                ext:    Extensions::default(),
+                clock:  clock,
+            });
         }
     }
 }
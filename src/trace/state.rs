@@ -2,18 +2,25 @@ use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::net::IpAddr;
 use std::ops::Deref;
+use std::time::{Duration, Instant};
 use rand::prelude::*;
 use rand::distributions::Uniform;
 use parking_lot::Mutex;
-use tokio::sync::broadcast::{Receiver, Sender, channel};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
 use tokio::task;
-use super::probe::{Key, Probes, Protocol, PORT_MIN, PORT_MAX};
+use super::probe::{Ident, Key, Probes, Protocol, PORT_MIN, PORT_MAX};
 use super::reply::Echo;
 
 #[derive(Debug)]
 pub struct State {
     range: Uniform<u16>,
-    state: Mutex<HashMap<Key, Sender<Echo>>>,
+    state: Mutex<HashMap<Key, Slot>>,
+}
+
+#[derive(Debug)]
+struct Slot {
+    txs:  Vec<UnboundedSender<(Ident, Echo)>>,
+    sent: HashMap<Ident, (u8, Instant, Instant)>,
 }
 
 #[derive(Debug)]
@@ -31,15 +38,13 @@ impl State {
     }
 
     pub async fn reserve(&self, proto: Protocol, src: IpAddr, dst: IpAddr) -> Lease<'_> {
-        let (tx, _) = channel(10);
-
         loop {
             let value  = thread_rng().sample(self.range);
             let probes = Probes::new(proto, src, dst, value);
             let key    = probes.key();
 
             if let Entry::Vacant(e) = self.state.lock().entry(key) {
-                e.insert(tx);
+                e.insert(Slot { txs: Vec::new(), sent: HashMap::new() });
                 return Lease::new(self, probes);
             }
 
@@ -47,12 +52,33 @@ impl State {
         }
     }
 
-    pub fn sender(&self, key: &Key) -> Option<Sender<Echo>> {
-        self.state.lock().get(key).map(Sender::clone)
+    pub fn deliver(&self, key: &Key, idents: &[Ident], echo: Echo) {
+        if let Some(slot) = self.state.lock().get_mut(key) {
+            if let Some(ident) = idents.iter().find(|ident| slot.sent.contains_key(ident)) {
+                slot.txs.retain(|tx| tx.send((*ident, echo.clone())).is_ok());
+            }
+        }
+    }
+
+    pub fn receiver(&self, key: &Key) -> Option<UnboundedReceiver<(Ident, Echo)>> {
+        let mut state = self.state.lock();
+        let slot = state.get_mut(key)?;
+        let (tx, rx) = unbounded_channel();
+        slot.txs.retain(|tx| !tx.is_closed());
+        slot.txs.push(tx);
+        Some(rx)
     }
 
-    pub fn receiver(&self, key: &Key) -> Option<Receiver<Echo>> {
-        self.state.lock().get(key).map(Sender::subscribe)
+    pub fn sent(&self, key: &Key, ident: Ident, ttl: u8, when: Instant, expiry: Duration) {
+        if let Some(slot) = self.state.lock().get_mut(key) {
+            slot.sent.retain(|_, &mut (_, _, until)| until > when);
+            slot.sent.insert(ident, (ttl, when, when + expiry + GRACE));
+        }
+    }
+
+    pub fn take(&self, key: &Key, ident: &Ident) -> Option<(u8, Instant)> {
+        let (ttl, when, _) = self.state.lock().get_mut(key)?.sent.remove(ident)?;
+        Some((ttl, when))
     }
 
     pub fn release(&self, key: &Key) {
@@ -80,3 +106,36 @@ impl Drop for Lease<'_> {
         self.state.release(&key);
     }
 }
+
+const GRACE: Duration = Duration::from_secs(5);
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    #[tokio::test]
+    async fn sent_evicts_expired() {
+        let state  = State::new();
+        let src    = IpAddr::from([192, 0, 2, 2]);
+        let dst    = IpAddr::from([192, 0, 2, 1]);
+        let lease  = state.reserve(Protocol::ICMP, src, dst).await;
+        let key    = lease.key();
+        let expiry = Duration::from_secs(1);
+        let now    = Instant::now();
+
+        state.sent(&key, Ident::ICMP(1), 1, now, expiry);
+        state.sent(&key, Ident::ICMP(2), 2, now + expiry, expiry);
+        assert_eq!(Some((1, now)), state.take(&key, &Ident::ICMP(1)));
+
+        state.sent(&key, Ident::ICMP(1), 1, now, expiry);
+        state.sent(&key, Ident::ICMP(3), 3, now + expiry + GRACE, expiry);
+        assert_eq!(None,                    state.take(&key, &Ident::ICMP(1)));
+        assert_eq!(Some((2, now + expiry)), state.take(&key, &Ident::ICMP(2)));
+
+        state.sent(&key, Ident::ICMP(4), 4, now + (expiry + GRACE) * 3, expiry);
+        assert_eq!(None, state.take(&key, &Ident::ICMP(3)));
+        assert_eq!(1,    state.state.lock()[&key].sent.len());
+    }
+}
@@ -1,25 +1,34 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use anyhow::Result;
-use futures::future;
-use futures::{Stream, StreamExt, TryStreamExt};
-use futures::stream::try_unfold;
-use tokio::time::timeout;
+use anyhow::{anyhow, Result};
+use futures::future::{self, select, Either, FutureExt};
+use futures::{pin_mut, Stream, StreamExt, TryStreamExt};
+use futures::stream::{try_unfold, BoxStream};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::timeout_at;
 use crate::Bind;
+use crate::limit::ticks;
 use super::icmp::Icmp;
-use super::probe::{Probe, Protocol, ICMP, TCP, UDP};
+use super::probe::{Ident, Key, Probe, Protocol, ICMP, TCP, UDP};
 use super::reply::{Echo, Node};
 use super::{sock4::Sock4, sock6::Sock6};
 use super::state::{Lease, State};
 
 #[derive(Debug)]
 pub struct Trace {
-    pub proto:  Protocol,
-    pub addr:   IpAddr,
-    pub probes: usize,
-    pub limit:  usize,
-    pub expiry: Duration,
+    pub proto:    Protocol,
+    pub addr:     IpAddr,
+    pub probes:   usize,
+    pub limit:    usize,
+    pub expiry:   Duration,
+    pub interval: Duration,
+    pub deadline: Option<Duration>,
+    pub paris:    bool,
+    pub window:   usize,
+    pub gap:      usize,
 }
 
 pub struct Tracer {
@@ -40,39 +49,225 @@ impl Tracer {
         Ok(Self { icmp, sock4, sock6, state })
     }
 
-    pub async fn route(&self, trace: Trace) -> Result<Vec<Vec<Node>>> {
-        let Trace { proto, addr, probes, limit, expiry } = trace;
+    pub async fn route(&self, trace: Trace) -> Result<Vec<Vec<Option<Node>>>> {
+        let Trace { proto, addr, probes, limit, expiry, interval, deadline, paris, window, gap } = trace;
 
         let source = self.reserve(proto, addr).await?;
 
-        let mut probe = source.probe()?;
-        let mut done  = false;
+        let mut done   = false;
+        let mut silent = 0;
+        let mut probe  = match paris {
+            true  => source.paris(0)?,
+            false => source.probe()?,
+        };
 
-        Ok(self.trace(&mut probe, probes, expiry).take_while(|result| {
+        if window > 1 {
+            return self.parallel(&mut probe, &trace).await;
+        }
+
+        self.trace(&mut probe, probes, expiry, interval, deadline).take_while(|result| {
             let last = done;
             if let Ok(nodes) = result {
-                done = nodes.iter().any(|node| {
-                    match node {
-                        Node::Node(_, ip, _, done) => *done || ip == &addr,
-                        Node::None(_)              => false,
-                    }
+                done = nodes.iter().flatten().any(|node| {
+                    node.last() || node.addr == addr
                 });
+                silent = match nodes.iter().all(Option::is_none) {
+                    true  => silent + 1,
+                    false => 0,
+                };
+                done |= gap > 0 && silent >= gap;
             }
             future::ready(!last)
-        }).take(limit).try_collect().await?)
+        }).take(limit).try_collect().await
+    }
+
+    pub async fn parallel(&self, probe: &mut Probe, trace: &Trace) -> Result<Vec<Vec<Option<Node>>>> {
+        let Trace { addr, probes: count, limit, expiry, interval, deadline, window, gap, .. } = *trace;
+
+        let key   = probe.key();
+        let limit = limit.min(usize::from(u8::MAX));
+        let stop  = deadline.map(|deadline| Instant::now() + deadline);
+
+        let mut rx = self.state.receiver(&key).ok_or_else(|| anyhow!("probe lease released"))?;
+
+        let mut hops        = vec![Vec::new(); limit];
+        let mut sent        = vec![0; limit];
+        let mut complete    = vec![0; limit];
+        let mut outstanding = HashMap::new();
+        let mut pending     = VecDeque::<(Instant, Ident, usize)>::new();
+        let mut next        = Instant::now();
+
+        loop {
+            let now = Instant::now();
+
+            while let Some(&(expires, ident, index)) = pending.front() {
+                if expires > now {
+                    break;
+                }
+
+                pending.pop_front();
+
+                if outstanding.remove(&ident).is_some() {
+                    hops[index].push(None);
+                    complete[index] += 1;
+                }
+            }
+
+            let last = last(&hops, &complete, count, addr, gap);
+
+            if complete[..last].iter().all(|&n| n >= count) {
+                break;
+            }
+
+            let first = complete[..last].iter().position(|&n| n < count).unwrap_or(last);
+            let open  = !matches!(stop, Some(stop) if now >= stop);
+            let index = (first..last.min(first + window)).filter(|&i| open && sent[i] < count);
+            let index = index.min_by_key(|&i| sent[i]);
+
+            if let (Some(index), true) = (index, now >= next) {
+                let ttl   = u8::try_from(index + 1)?;
+                let ident = probe.ident();
+
+                self.state.sent(&key, ident, ttl, now, expiry);
+                let when = self.send(probe, ttl).await?;
+                probe.increment();
+
+                outstanding.insert(ident, index);
+                pending.push_back((when + expiry, ident, index));
+                sent[index] += 1;
+                next = now + interval;
+
+                continue;
+            }
+
+            let wake = match (pending.front(), index) {
+                (Some(&(expires, ..)), Some(_)) => expires.min(next),
+                (Some(&(expires, ..)), None)    => expires,
+                (None,                 Some(_)) => next,
+                (None,                 None)    => break,
+            };
+
+            match timeout_at(wake.into(), rx.recv()).await {
+                Ok(Some((ident, echo))) => {
+                    let live = outstanding.remove(&ident).is_some();
+
+                    if let Some((ttl, sent)) = self.state.take(&key, &ident) {
+                        let index = usize::from(ttl) - 1;
+                        match live {
+                            true  => hops[index].push(Some(Node::new(ttl, echo, sent))),
+                            false => hops[index].push(Some(Node::late(ttl, echo, sent))),
+                        }
+                        complete[index] += usize::from(live);
+                    }
+                },
+                Ok(None) => break,
+                Err(_)   => (),
+            }
+        }
+
+        let last = last(&hops, &complete, count, addr, gap);
+        hops.truncate(last);
+
+        while let Some(true) = hops.last().map(Vec::is_empty) {
+            hops.pop();
+        }
+
+        Ok(hops)
     }
 
     pub fn trace<'a>(
         &'a self,
-        probe:  &'a mut Probe,
+        probe:    &'a mut Probe,
+        count:    usize,
+        expiry:   Duration,
+        interval: Duration,
+        deadline: Option<Duration>,
+    ) -> impl Stream<Item = Result<Vec<Option<Node>>>> + 'a {
+        let ticks = ticks(interval, deadline).boxed();
+
+        try_unfold((probe, 1, ticks), move |(probe, ttl, mut ticks)| async move {
+            let result = self.hop(probe, ttl, count, expiry, &mut ticks).await?;
+
+            if result.is_empty() {
+                return Ok(None);
+            }
+
+            Ok(Some((result, (probe, ttl + 1, ticks))))
+        })
+    }
+
+    async fn hop(
+        &self,
+        probe:  &mut Probe,
+        ttl:    u8,
         count:  usize,
         expiry: Duration,
-    ) -> impl Stream<Item = Result<Vec<Node>>> + 'a {
-        try_unfold((probe, 1), move |(probe, ttl)| async move {
-            let stream = self.probe(probe, ttl, expiry);
-            let result = stream.take(count).try_collect().await?;
-            Ok(Some((result, (probe, ttl + 1))))
-        })
+        ticks:  &mut BoxStream<'_, Instant>,
+    ) -> Result<Vec<Option<Node>>> {
+        let key = probe.key();
+
+        let mut rx = self.state.receiver(&key).ok_or_else(|| anyhow!("probe lease released"))?;
+
+        let mut nodes   = Vec::new();
+        let mut late    = Vec::new();
+        let mut pending = VecDeque::<(Instant, Ident, usize, Instant)>::new();
+        let mut open    = true;
+
+        loop {
+            let now = Instant::now();
+
+            while let Some(&(expires, ..)) = pending.front() {
+                if expires > now {
+                    break;
+                }
+                pending.pop_front();
+            }
+
+            let more = open && nodes.len() < count;
+
+            if !more && pending.is_empty() {
+                break;
+            }
+
+            let wake = pending.front().map_or(now + expiry, |&(expires, ..)| expires);
+            let recv = timeout_at(wake.into(), rx.recv());
+            pin_mut!(recv);
+
+            let tick = match more {
+                true  => ticks.next().left_future(),
+                false => future::pending().right_future(),
+            };
+
+            match select(tick, recv).await {
+                Either::Left((Some(_), _)) => {
+                    let ident = probe.ident();
+
+                    self.state.sent(&key, ident, ttl, Instant::now(), expiry);
+                    let sent = self.send(probe, ttl).await?;
+                    probe.increment();
+
+                    pending.push_back((sent + expiry, ident, nodes.len(), sent));
+                    nodes.push(None);
+                },
+                Either::Left((None, _)) => open = false,
+                Either::Right((Ok(Some((id, echo))), _)) => {
+                    let index = pending.iter().position(|&(_, ident, ..)| ident == id);
+                    match (self.state.take(&key, &id), index.and_then(|i| pending.remove(i))) {
+                        (Some(_), Some((_, _, index, sent))) => nodes[index] = Some(Node::new(ttl, echo, sent)),
+                        (Some((ttl, sent)), None)            => late.push(Node::late(ttl, echo, sent)),
+                        _                                    => (),
+                    }
+                },
+                Either::Right((Ok(None), _)) => break,
+                Either::Right((Err(_), _))   => (),
+            }
+        }
+
+        if nodes.is_empty() {
+            return Ok(nodes);
+        }
+
+        Ok(late.into_iter().map(Some).chain(nodes).collect())
     }
 
     pub fn probe<'a>(
@@ -80,29 +275,56 @@ impl Tracer {
         probe:  &'a mut Probe,
         ttl:    u8,
         expiry: Duration,
-    ) -> impl Stream<Item = Result<Node>> + 'a {
-        try_unfold(probe, move |probe| async move {
-            let sent = self.send(probe, ttl).await?;
-            let recv = self.recv(probe);
-            let echo = timeout(expiry, recv).await;
-
-            probe.increment();
-
-            if let Ok(Some(Echo(addr, when, last))) = echo {
-                let rtt  = when.saturating_duration_since(sent);
-                let node = Node::Node(ttl, addr, rtt, last);
-                return Ok(Some((node, probe)))
+    ) -> impl Stream<Item = Result<Option<Node>>> + 'a {
+        try_unfold((probe, VecDeque::new()), move |(probe, mut pending)| async move {
+            if pending.is_empty() {
+                let key   = probe.key();
+                let ident = probe.ident();
+                let rx    = self.state.receiver(&key);
+
+                self.state.sent(&key, ident, ttl, Instant::now(), expiry);
+
+                let sent = self.send(probe, ttl).await?;
+                let (node, late) = self.recv(rx, &key, ident, ttl, sent, expiry).await;
+
+                probe.increment();
+
+                pending.extend(late.into_iter().map(Some));
+                pending.push_back(node);
             }
 
-            Ok(Some((Node::None(ttl), probe)))
+            let node = pending.pop_front().flatten();
+
+            Ok(Some((node, (probe, pending))))
         })
     }
 
+    pub async fn once(&self, probe: &mut Probe, ttl: u8, expiry: Duration) -> Result<Option<Node>> {
+        let stream = self.probe(probe, ttl, expiry);
+        pin_mut!(stream);
+
+        while let Some(node) = stream.try_next().await? {
+            match node {
+                Some(node) if node.late => continue,
+                node                    => return Ok(node),
+            }
+        }
+
+        Ok(None)
+    }
+
     pub async fn reserve(&self, proto: Protocol, addr: IpAddr) -> Result<Lease<'_>> {
         let src = self.source(addr).await?;
         Ok(self.state.reserve(proto, src, addr).await)
     }
 
+    pub async fn mtu(&self, addr: IpAddr) -> Result<usize> {
+        match addr {
+            IpAddr::V4(..) => self.sock4.mtu(addr).await,
+            IpAddr::V6(..) => self.sock6.mtu(addr).await,
+        }
+    }
+
     async fn send(&self, probe: &Probe, ttl: u8) -> Result<Instant> {
         match probe {
             Probe::ICMP(ICMP::V4(_)) => self.sock4.send(probe, ttl).await,
@@ -114,8 +336,36 @@ impl Tracer {
         }
     }
 
-    async fn recv(&self, probe: &Probe) -> Option<Echo> {
-        self.state.receiver(&probe.key())?.recv().await.ok()
+    async fn recv(
+        &self,
+        rx:     Option<UnboundedReceiver<(Ident, Echo)>>,
+        key:    &Key,
+        ident:  Ident,
+        ttl:    u8,
+        sent:   Instant,
+        expiry: Duration,
+    ) -> (Option<Node>, Vec<Node>) {
+        let mut late = Vec::new();
+
+        let mut rx = match rx {
+            Some(rx) => rx,
+            None     => return (None, late),
+        };
+
+        loop {
+            match timeout_at((sent + expiry).into(), rx.recv()).await {
+                Ok(Some((id, echo))) if id == ident => {
+                    self.state.take(key, &id);
+                    return (Some(Node::new(ttl, echo, sent)), late);
+                },
+                Ok(Some((id, echo))) => {
+                    if let Some((ttl, sent)) = self.state.take(key, &id) {
+                        late.push(Node::late(ttl, echo, sent));
+                    }
+                },
+                _ => return (None, late),
+            }
+        }
     }
 
     async fn source(&self, dst: IpAddr) -> Result<IpAddr> {
@@ -132,3 +382,24 @@ impl Drop for Tracer {
         self.icmp.recv6.abort();
     }
 }
+
+fn last(hops: &[Vec<Option<Node>>], complete: &[usize], count: usize, addr: IpAddr, gap: usize) -> usize {
+    let mut silent = 0;
+
+    for (index, hop) in hops.iter().enumerate() {
+        if hop.iter().flatten().any(|node| node.last() || node.addr == addr) {
+            return index + 1;
+        }
+
+        silent = match complete[index] >= count && hop.iter().all(Option::is_none) {
+            true  => silent + 1,
+            false => 0,
+        };
+
+        if gap > 0 && silent >= gap {
+            return index + 1;
+        }
+    }
+
+    hops.len()
+}